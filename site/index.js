@@ -1,3 +1,19 @@
 import * as pots from "pots";
 
-pots.main();
+// Run the Prover and the Verifier against each other using the serialized
+// messages that would be sent between them over the network.
+const params = new pots.ProtoParams(4096);
+const proverId = new Uint8Array([0xaa, 0xbb]);
+const verifier = new pots.Verifier(params, new Uint8Array([]), proverId);
+const prover = new pots.Prover(params, verifier.nonce, proverId);
+
+verifier.setCommitment(prover.commitment());
+const challenge = verifier.genChallenge();
+const proofs = prover.createProofs(challenge);
+
+try {
+  verifier.verifyProofs(proofs);
+  console.log("res => Ok(())");
+} catch (e) {
+  console.log(`res => Err(${e})`);
+}
@@ -0,0 +1,242 @@
+//! JavaScript classes wrapping the protocol parameters, the Prover and the
+//! Verifier. Messages passed between the Prover and the Verifier are
+//! serialized into `Uint8Array`s so that they can be sent between tabs,
+//! workers or over the network.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use crate::error::Error;
+use crate::init::Initializer;
+use crate::merkle::{Arity, MerkleProof};
+use crate::params::{ProtoParams, Space};
+use crate::prover::{Commitment, Prover};
+use crate::verifier::Verifier;
+
+fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, JsValue> {
+    bincode::serialize(value)
+        .map_err(|e| JsValue::from_str(&format!("could not encode: {}", e)))
+}
+
+fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, JsValue> {
+    bincode::deserialize(bytes)
+        .map_err(|e| JsValue::from_str(&format!("could not decode: {}", e)))
+}
+
+fn to_js(e: Error) -> JsValue {
+    JsValue::from_str(&e.to_string())
+}
+
+#[wasm_bindgen(js_name = ProtoParams)]
+pub struct JsProtoParams(ProtoParams);
+
+#[wasm_bindgen(js_class = ProtoParams)]
+impl JsProtoParams {
+    /// Derives the protocol parameters for a space requirement of `n_bytes`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(n_bytes: usize) -> Result<JsProtoParams, JsValue> {
+        ProtoParams::new(Space::Bytes(n_bytes))
+            .map(JsProtoParams)
+            .map_err(to_js)
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn space(&self) -> usize {
+        self.0.space
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn n(&self) -> usize {
+        self.0.n
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn k(&self) -> usize {
+        self.0.k
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn delta(&self) -> f32 {
+        self.0.delta
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn l0(&self) -> usize {
+        self.0.l0
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn arity(&self) -> usize {
+        self.0.arity.n_children()
+    }
+
+    /// Sets the arity of the Prover's Merkle tree to 2, 4 or 8.
+    #[wasm_bindgen(setter)]
+    pub fn set_arity(&mut self, arity: usize) -> Result<(), JsValue> {
+        self.0.arity = match arity {
+            2 => Arity::Binary,
+            4 => Arity::Quaternary,
+            8 => Arity::Octal,
+            _ => {
+                let msg = format!("unsupported arity: {}", arity);
+                return Err(JsValue::from_str(&msg));
+            }
+        };
+        Ok(())
+    }
+}
+
+#[wasm_bindgen(js_name = Prover)]
+pub struct JsProver(Prover);
+
+#[wasm_bindgen(js_class = Prover)]
+impl JsProver {
+    /// Creates a Prover whose labels are keyed by `prover_id` (e.g. its
+    /// public key bytes).
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        params: &JsProtoParams,
+        nonce: &[u8],
+        prover_id: &[u8],
+    ) -> Result<JsProver, JsValue> {
+        let params = params.0.clone();
+        Prover::new(params, nonce.to_vec(), prover_id.to_vec())
+            .map(JsProver)
+            .map_err(to_js)
+    }
+
+    /// Returns the serialized commitment to send to the Verifier.
+    pub fn commitment(&self) -> Result<Vec<u8>, JsValue> {
+        encode(&self.0.commitment())
+    }
+
+    /// Answers a serialized challenge, returning the serialized proofs.
+    #[wasm_bindgen(js_name = createProofs)]
+    pub fn create_proofs(
+        &mut self,
+        challenge: &[u8],
+    ) -> Result<Vec<u8>, JsValue> {
+        let challenge_indices: Vec<usize> = decode(challenge)?;
+        encode(&self.0.create_proofs(&challenge_indices).map_err(to_js)?)
+    }
+}
+
+/// Initializes a Prover a bounded number of vertices at a time so that the
+/// page stays responsive.
+#[wasm_bindgen(js_name = ProverInit)]
+pub struct JsInitializer(Initializer);
+
+#[wasm_bindgen(js_class = ProverInit)]
+impl JsInitializer {
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        params: &JsProtoParams,
+        nonce: &[u8],
+        prover_id: &[u8],
+    ) -> Result<JsInitializer, JsValue> {
+        let params = params.0.clone();
+        Initializer::new(params, nonce.to_vec(), prover_id.to_vec())
+            .map(JsInitializer)
+            .map_err(to_js)
+    }
+
+    /// Resumes from the state returned by `serialize`, throwing if the state
+    /// is corrupted.
+    pub fn deserialize(state: &[u8]) -> Result<JsInitializer, JsValue> {
+        let initializer: Initializer = decode(state)?;
+        initializer.validate().map_err(to_js)?;
+        Ok(JsInitializer(initializer))
+    }
+
+    pub fn serialize(&self) -> Result<Vec<u8>, JsValue> {
+        encode(&self.0)
+    }
+
+    /// Labels up to `max_vertices` more vertices, returning the fraction of
+    /// the initialization completed so far.
+    pub fn step(&mut self, max_vertices: usize) -> f32 {
+        self.0.step(max_vertices)
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn progress(&self) -> f32 {
+        self.0.progress()
+    }
+
+    #[wasm_bindgen(getter, js_name = isDone)]
+    pub fn is_done(&self) -> bool {
+        self.0.is_done()
+    }
+
+    pub fn cancel(&mut self) {
+        self.0.cancel()
+    }
+
+    /// Consumes the initializer and returns the Prover, throwing if the
+    /// initialization has not completed.
+    pub fn finish(self) -> Result<JsProver, JsValue> {
+        self.0.finish().map(JsProver).map_err(|_| {
+            JsValue::from_str("prover initialization has not completed")
+        })
+    }
+}
+
+#[wasm_bindgen(js_name = Verifier)]
+pub struct JsVerifier(Verifier);
+
+#[wasm_bindgen(js_class = Verifier)]
+impl JsVerifier {
+    /// Creates a Verifier that only accepts proofs from the Prover
+    /// `prover_id`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        params: &JsProtoParams,
+        nonce: &[u8],
+        prover_id: &[u8],
+    ) -> Result<JsVerifier, JsValue> {
+        let params = params.0.clone();
+        Verifier::new(params, nonce.to_vec(), prover_id.to_vec())
+            .map(JsVerifier)
+            .map_err(to_js)
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn nonce(&self) -> Vec<u8> {
+        self.0.nonce().to_vec()
+    }
+
+    /// Accepts the Prover's serialized commitment. The commitment must have
+    /// been made for this Verifier's parameters, nonce and prover identity.
+    #[wasm_bindgen(js_name = setCommitment)]
+    pub fn set_commitment(&mut self, commitment: &[u8]) -> Result<(), JsValue> {
+        let commitment: Commitment = decode(commitment)?;
+        if &commitment.params != self.0.params()
+            || commitment.nonce != self.0.nonce()
+            || commitment.prover_id != self.0.prover_id()
+        {
+            let msg = "commitment was made for different params, nonce or \
+                       prover identity";
+            return Err(JsValue::from_str(msg));
+        }
+        self.0
+            .set_graph_description(commitment.edges, commitment.merkle_root)
+            .map_err(to_js)
+    }
+
+    /// Generates a new challenge, returning it serialized.
+    #[wasm_bindgen(js_name = genChallenge)]
+    pub fn gen_challenge(&mut self) -> Result<Vec<u8>, JsValue> {
+        encode(&self.0.gen_challenge())
+    }
+
+    /// Verifies the Prover's serialized answer to the last challenge, throwing
+    /// if verification fails.
+    #[wasm_bindgen(js_name = verifyProofs)]
+    pub fn verify_proofs(&mut self, proofs: &[u8]) -> Result<(), JsValue> {
+        let proofs: Vec<MerkleProof> = decode(proofs)?;
+        self.0
+            .verify_proofs(&proofs)
+            .map_err(|e| JsValue::from_str(&format!("{:?}", e)))
+    }
+}
@@ -0,0 +1,616 @@
+//! Sources of challenges.
+//!
+//! By default the Verifier samples its challenges from private randomness, so
+//! only the Verifier itself can vouch for them. A challenge can instead be
+//! derived from the Prover's commitment (Fiat–Shamir) or from the commitment
+//! together with a public randomness beacon (e.g. a beacon round or a block
+//! hash), in which case anyone can rederive it. A `PublicProof` records where
+//! its challenge came from, so that any Verifier can check it.
+//!
+//! Whatever its source, a challenge is spread over the graph's columns
+//! according to the parameters' `ChallengeDistribution`. Derived challenges
+//! are sampled from a SHA3-based RNG using this module's own sampling
+//! routines, so that they depend only on SHA3 and not on the algorithms of a
+//! particular version of `rand`.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use rand::rngs::OsRng;
+use rand::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+
+use crate::hasher::Hasher;
+use crate::merkle::MerkleProof;
+use crate::params::ProtoParams;
+
+/// How challenged labels are spread over the graph's columns. The labels in
+/// the first column are computed from the nonce alone, so challenging them
+/// says little about the space the Prover stores.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub enum ChallengeDistribution {
+    /// Every label is equally likely to be challenged.
+    #[default]
+    Uniform,
+    /// Only labels in the final column are challenged, so a Prover that
+    /// stores only that column answers every challenge: the Prover proves
+    /// that it stores `ProtoParams::proven_space` bytes rather than the full
+    /// space.
+    LastColumn,
+    /// The same number of labels is challenged in every column.
+    Stratified,
+    /// Column `c` is challenged with probability proportional to `c + 1`.
+    DeepWeighted,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ChallengeError {
+    /// The beacon has not published the requested round.
+    BeaconRoundUnavailable { round: u64 },
+    /// The challenge contains `index` more than once.
+    DuplicateIndex { index: usize },
+    /// The beacon has not published any rounds.
+    EmptyBeacon,
+    /// The challenged `index` is not one of the `n_indices` labels (or
+    /// blocks) that can be challenged, e.g. it names a padding leaf of the
+    /// Merkle tree.
+    IndexOutOfRange { index: usize, n_indices: usize },
+    /// A saved challenge's tag does not authenticate it under the Verifier's
+    /// key and the Prover's commitment.
+    InvalidTag,
+    /// The challenge has `actual` indices rather than `expected`.
+    UnexpectedLength { expected: usize, actual: usize },
+}
+
+/// Where a challenge came from.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub enum ChallengeOrigin {
+    /// The Verifier's private randomness; the challenge cannot be rederived.
+    Random,
+    /// The Prover's commitment.
+    FiatShamir,
+    /// The Prover's commitment and the beacon's randomness for `round`.
+    Beacon { round: u64 },
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Challenge {
+    pub indices: Vec<usize>,
+    pub origin: ChallengeOrigin,
+}
+
+/// The Prover's answer to a challenge, along with the challenge's origin.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PublicProof {
+    pub origin: ChallengeOrigin,
+    pub proofs: Vec<MerkleProof>,
+}
+
+/// A deterministic RNG whose output is the concatenation of
+/// `SHA3-256(seed || counter)` for `counter = 0, 1, ...`.
+#[derive(Debug)]
+pub(crate) struct ChallengeRng {
+    seed: Vec<u8>,
+    counter: u64,
+    block: Vec<u8>,
+    /// The number of bytes of `block` already output.
+    used: usize,
+}
+
+impl ChallengeRng {
+    pub(crate) fn new(seed: &[u8]) -> Self {
+        ChallengeRng {
+            seed: seed.to_vec(),
+            counter: 0,
+            block: vec![],
+            used: 0,
+        }
+    }
+}
+
+impl RngCore for ChallengeRng {
+    fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0u8; 4];
+        self.fill_bytes(&mut bytes);
+        u32::from_be_bytes(bytes)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0u8; 8];
+        self.fill_bytes(&mut bytes);
+        u64::from_be_bytes(bytes)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for byte in dest.iter_mut() {
+            if self.used == self.block.len() {
+                self.block =
+                    Hasher::new().challenge_block(&self.seed, self.counter);
+                self.counter += 1;
+                self.used = 0;
+            }
+            *byte = self.block[self.used];
+            self.used += 1;
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl CryptoRng for ChallengeRng {}
+
+/// Samples an index in `0..bound` (which must be non-zero) without modulo
+/// bias, by rejecting the draws past the largest multiple of `bound`.
+fn below<R: RngCore + ?Sized>(rng: &mut R, bound: usize) -> usize {
+    let bound = bound as u64;
+    let zone = u64::MAX - u64::MAX % bound;
+    loop {
+        let draw = rng.next_u64();
+        if draw < zone {
+            return (draw % bound) as usize;
+        }
+    }
+}
+
+/// Samples `amount` distinct indices in `0..n` using Floyd's algorithm, which
+/// uses memory proportional to `amount` rather than to `n`.
+fn sample_distinct<R: RngCore + ?Sized>(
+    rng: &mut R,
+    n: usize,
+    amount: usize,
+) -> Vec<usize> {
+    let mut seen = HashSet::with_capacity(amount);
+    let mut indices = Vec::with_capacity(amount);
+    for j in (n - amount)..n {
+        let t = below(rng, j + 1);
+        let index = if seen.contains(&t) { j } else { t };
+        seen.insert(index);
+        indices.push(index);
+    }
+    indices
+}
+
+/// Samples `l0` distinct label indices according to the parameters'
+/// challenge distribution, using memory proportional to `l0`.
+pub(crate) fn sample_challenge<R: RngCore + ?Sized>(
+    params: &ProtoParams,
+    rng: &mut R,
+) -> Vec<usize> {
+    let (n, k) = (params.n, params.k);
+    match params.distribution {
+        ChallengeDistribution::Uniform => {
+            sample_distinct(rng, n * k, params.l0.min(n * k))
+        }
+        ChallengeDistribution::LastColumn => {
+            sample_distinct(rng, n, params.l0.min(n))
+                .into_iter()
+                .map(|vertex| (k - 1) * n + vertex)
+                .collect()
+        }
+        ChallengeDistribution::Stratified => {
+            let per_col = params.l0.div_ceil(k).min(n);
+            (0..k)
+                .flat_map(|col| {
+                    sample_distinct(rng, n, per_col)
+                        .into_iter()
+                        .map(move |vertex| col * n + vertex)
+                        .collect::<Vec<usize>>()
+                })
+                .collect()
+        }
+        ChallengeDistribution::DeepWeighted => {
+            // Rejects repeated labels; `l0` is capped at `n * k`.
+            let total_weight = k * (k + 1) / 2;
+            let n_challenged = params.l0.min(n * k);
+            let mut seen = HashSet::with_capacity(n_challenged);
+            let mut challenge = Vec::with_capacity(n_challenged);
+            while challenge.len() < n_challenged {
+                let mut weight = below(rng, total_weight);
+                let mut col = 0;
+                while weight > col {
+                    weight -= col + 1;
+                    col += 1;
+                }
+                let index = col * n + below(rng, n);
+                if seen.insert(index) {
+                    challenge.push(index);
+                }
+            }
+            challenge
+        }
+    }
+}
+
+/// The number of labels in a challenge sampled for `params`.
+pub fn challenge_len(params: &ProtoParams) -> usize {
+    let (n, k) = (params.n, params.k);
+    match params.distribution {
+        ChallengeDistribution::Uniform
+        | ChallengeDistribution::DeepWeighted => params.l0.min(n * k),
+        ChallengeDistribution::LastColumn => params.l0.min(n),
+        ChallengeDistribution::Stratified => params.l0.div_ceil(k).min(n) * k,
+    }
+}
+
+/// Checks a challenge received from the Verifier before answering it: it must
+/// contain `challenge_len(params)` distinct indices, each naming one of the
+/// graph's `n * k` labels.
+pub fn check_challenge(
+    params: &ProtoParams,
+    indices: &[usize],
+) -> Result<(), ChallengeError> {
+    check_indices(indices, params.n * params.k, challenge_len(params))
+}
+
+/// Checks that `indices` holds `expected_len` distinct indices less than
+/// `n_indices`.
+pub(crate) fn check_indices(
+    indices: &[usize],
+    n_indices: usize,
+    expected_len: usize,
+) -> Result<(), ChallengeError> {
+    if indices.len() != expected_len {
+        return Err(ChallengeError::UnexpectedLength {
+            expected: expected_len,
+            actual: indices.len(),
+        });
+    }
+    let mut seen = HashSet::with_capacity(indices.len());
+    for &index in indices {
+        if index >= n_indices {
+            return Err(ChallengeError::IndexOutOfRange { index, n_indices });
+        }
+        if !seen.insert(index) {
+            return Err(ChallengeError::DuplicateIndex { index });
+        }
+    }
+    Ok(())
+}
+
+/// Samples a challenge using a `ChallengeRng` seeded with `seed`.
+pub(crate) fn sample_from_seed(
+    params: &ProtoParams,
+    seed: &[u8],
+) -> Vec<usize> {
+    sample_challenge(params, &mut ChallengeRng::new(seed))
+}
+
+/// Derives the challenge for the commitment with digest `commitment_digest`
+/// (see `Hasher::commitment_digest`) from the public `randomness` (empty for
+/// Fiat–Shamir challenges).
+pub fn derive_challenge(
+    params: &ProtoParams,
+    commitment_digest: &[u8],
+    randomness: &[u8],
+) -> Vec<usize> {
+    let seed = Hasher::new().challenge_seed(commitment_digest, randomness);
+    sample_from_seed(params, &seed)
+}
+
+/// Something that produces challenges for a committed Prover.
+pub trait ChallengeSource {
+    fn challenge(
+        &mut self,
+        params: &ProtoParams,
+        commitment_digest: &[u8],
+    ) -> Result<Challenge, ChallengeError>;
+}
+
+/// Samples challenges from private randomness using `R`, which defaults to
+/// the operating system's RNG.
+#[derive(Debug)]
+pub struct RandomSource<R = OsRng> {
+    rng: R,
+}
+
+impl RandomSource {
+    pub fn new() -> crate::Result<Self> {
+        Ok(RandomSource::with_rng(OsRng::new()?))
+    }
+}
+
+impl<R: RngCore + CryptoRng> RandomSource<R> {
+    pub fn with_rng(rng: R) -> Self {
+        RandomSource { rng }
+    }
+}
+
+impl<R: RngCore + CryptoRng> ChallengeSource for RandomSource<R> {
+    fn challenge(
+        &mut self,
+        params: &ProtoParams,
+        _commitment_digest: &[u8],
+    ) -> Result<Challenge, ChallengeError> {
+        let indices = sample_challenge(params, &mut self.rng);
+        Ok(Challenge { indices, origin: ChallengeOrigin::Random })
+    }
+}
+
+/// Derives challenges from the Prover's commitment alone, making the protocol
+/// non-interactive.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FiatShamirSource;
+
+impl ChallengeSource for FiatShamirSource {
+    fn challenge(
+        &mut self,
+        params: &ProtoParams,
+        commitment_digest: &[u8],
+    ) -> Result<Challenge, ChallengeError> {
+        Ok(Challenge {
+            indices: derive_challenge(params, commitment_digest, &[]),
+            origin: ChallengeOrigin::FiatShamir,
+        })
+    }
+}
+
+/// A public source of randomness that publishes a value per round.
+pub trait Beacon {
+    /// The value published for `round`, if it has been published.
+    fn randomness(&self, round: u64) -> Option<Vec<u8>>;
+
+    /// The most recently published round.
+    fn latest_round(&self) -> Option<u64>;
+}
+
+/// Derives challenges from the Prover's commitment and a beacon round. Unless
+/// a round is set, the beacon's latest round is used.
+#[derive(Debug)]
+pub struct BeaconSource<B> {
+    beacon: B,
+    round: Option<u64>,
+}
+
+impl<B: Beacon> BeaconSource<B> {
+    pub fn new(beacon: B) -> Self {
+        BeaconSource { beacon, round: None }
+    }
+
+    pub fn with_round(mut self, round: u64) -> Self {
+        self.round = Some(round);
+        self
+    }
+
+    pub fn beacon(&self) -> &B {
+        &self.beacon
+    }
+}
+
+impl<B: Beacon> ChallengeSource for BeaconSource<B> {
+    fn challenge(
+        &mut self,
+        params: &ProtoParams,
+        commitment_digest: &[u8],
+    ) -> Result<Challenge, ChallengeError> {
+        let round = match self.round {
+            Some(round) => round,
+            None => {
+                self.beacon.latest_round().ok_or(ChallengeError::EmptyBeacon)?
+            }
+        };
+        let randomness = self
+            .beacon
+            .randomness(round)
+            .ok_or(ChallengeError::BeaconRoundUnavailable { round })?;
+        Ok(Challenge {
+            indices: derive_challenge(params, commitment_digest, &randomness),
+            origin: ChallengeOrigin::Beacon { round },
+        })
+    }
+}
+
+/// A local beacon for testing, backed by a file that holds one hex-encoded
+/// value per line; the value on line `i` (counting from zero) is published for
+/// round `i`.
+#[derive(Debug)]
+pub struct FileBeacon {
+    path: PathBuf,
+    rounds: Vec<Vec<u8>>,
+}
+
+impl FileBeacon {
+    /// Reads the beacon's rounds from `path`, which is created if it does not
+    /// exist.
+    pub fn open(path: impl AsRef<Path>) -> crate::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                fs::write(&path, "")?;
+                String::new()
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let rounds = contents
+            .lines()
+            .map(|line| parse_hex(line.trim()))
+            .collect::<io::Result<_>>()?;
+        Ok(FileBeacon { path, rounds })
+    }
+
+    /// Publishes `randomness` as the next round, appending it to the file.
+    /// Returns the new round.
+    pub fn publish(&mut self, randomness: &[u8]) -> crate::Result<u64> {
+        let line: String =
+            randomness.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let mut file = fs::OpenOptions::new().append(true).open(&self.path)?;
+        writeln!(file, "{}", line)?;
+        self.rounds.push(randomness.to_vec());
+        Ok(self.rounds.len() as u64 - 1)
+    }
+}
+
+impl Beacon for FileBeacon {
+    fn randomness(&self, round: u64) -> Option<Vec<u8>> {
+        self.rounds.get(round as usize).cloned()
+    }
+
+    fn latest_round(&self) -> Option<u64> {
+        self.rounds.len().checked_sub(1).map(|round| round as u64)
+    }
+}
+
+fn parse_hex(s: &str) -> io::Result<Vec<u8>> {
+    let invalid =
+        || io::Error::new(io::ErrorKind::InvalidData, "invalid hex in beacon");
+    if !s.is_ascii() || !s.len().is_multiple_of(2) {
+        return Err(invalid());
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).map_err(|_| invalid()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use sha3::{Digest, Sha3_256};
+
+    use super::*;
+    use crate::params::Space;
+
+    const DISTRIBUTIONS: [ChallengeDistribution; 4] = [
+        ChallengeDistribution::Uniform,
+        ChallengeDistribution::LastColumn,
+        ChallengeDistribution::Stratified,
+        ChallengeDistribution::DeepWeighted,
+    ];
+
+    /// Parameters for a graph of `n` vertices per column and `k` columns
+    /// from which `l0` labels are challenged.
+    fn params(n: usize, k: usize, l0: usize) -> ProtoParams {
+        ProtoParams {
+            n,
+            k,
+            l0,
+            ..ProtoParams::new(Space::Kbs(8)).unwrap()
+        }
+    }
+
+    #[test]
+    fn challenge_rng_is_sha3_in_counter_mode() {
+        let seed = b"seed";
+        let mut expected = vec![];
+        for counter in 0u64..2 {
+            let mut sha3 = Sha3_256::new();
+            sha3.input(seed);
+            sha3.input(counter.to_be_bytes());
+            expected.extend(sha3.result());
+        }
+        let mut output = vec![0u8; 40];
+        ChallengeRng::new(seed).fill_bytes(&mut output);
+        assert_eq!(output[..], expected[..40]);
+    }
+
+    #[test]
+    fn derived_challenges_are_pinned() {
+        // Changing how challenges are derived from a seed breaks every
+        // public proof made before the change.
+        let challenge = sample_from_seed(&params(1000, 6, 8), &[0; 32]);
+        assert_eq!(challenge, [4416, 1115, 1348, 5497, 2812, 839, 1689, 5859]);
+    }
+
+    #[test]
+    fn uniform_challenges_pass_a_chi_square_test() {
+        let (n, k, l0) = (64, 4, 32);
+        let params = params(n, k, l0);
+        let mut rng = StdRng::seed_from_u64(0);
+        let n_challenges = 2000;
+        let mut counts = vec![0usize; n * k];
+        for _ in 0..n_challenges {
+            for index in sample_challenge(&params, &mut rng) {
+                counts[index] += 1;
+            }
+        }
+        let expected = (n_challenges * l0) as f64 / (n * k) as f64;
+        let chi_square: f64 = counts
+            .iter()
+            .map(|&count| (count as f64 - expected).powi(2) / expected)
+            .sum();
+        // The critical value for 255 degrees of freedom at p = 0.001.
+        assert!(chi_square < 330.5, "chi-square {}", chi_square);
+    }
+
+    #[test]
+    fn challenges_are_well_formed() {
+        for distribution in &DISTRIBUTIONS {
+            for &(n, l0) in &[(64, 10), (64, 64 * 4), (3, 10)] {
+                let params = ProtoParams {
+                    distribution: *distribution,
+                    ..params(n, 4, l0)
+                };
+                let mut rng = StdRng::seed_from_u64(1);
+                for _ in 0..20 {
+                    let challenge = sample_challenge(&params, &mut rng);
+                    assert_eq!(check_challenge(&params, &challenge), Ok(()));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sampling_uses_memory_proportional_to_l0() {
+        // A challenge over 2^40 labels per column could not be sampled by
+        // materializing the labels' indices.
+        let n = 1 << 40;
+        for distribution in &DISTRIBUTIONS {
+            let params = ProtoParams {
+                distribution: *distribution,
+                ..params(n, 6, 50)
+            };
+            let challenge = sample_from_seed(&params, &[1; 32]);
+            assert_eq!(challenge.len(), challenge_len(&params));
+            assert_eq!(check_challenge(&params, &challenge), Ok(()));
+        }
+    }
+
+    /// The number of challenged labels in each column over `n_challenges`
+    /// challenges.
+    fn column_counts(params: &ProtoParams, n_challenges: usize) -> Vec<usize> {
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut counts = vec![0; params.k];
+        for _ in 0..n_challenges {
+            for index in sample_challenge(params, &mut rng) {
+                counts[index / params.n] += 1;
+            }
+        }
+        counts
+    }
+
+    #[test]
+    fn distributions_spread_challenges_over_columns() {
+        let (n, k, l0) = (256, 4, 32);
+        let with = |distribution| ProtoParams {
+            distribution,
+            ..params(n, k, l0)
+        };
+
+        let uniform = column_counts(&with(ChallengeDistribution::Uniform), 500);
+        let mean = (500 * l0 / k) as f64;
+        for count in uniform {
+            assert!((count as f64 - mean).abs() < 0.1 * mean);
+        }
+
+        let last = column_counts(&with(ChallengeDistribution::LastColumn), 50);
+        assert_eq!(last, [0, 0, 0, 50 * l0]);
+
+        let stratified = with(ChallengeDistribution::Stratified);
+        assert_eq!(column_counts(&stratified, 50), [50 * l0 / k; 4]);
+
+        // Column `c` is challenged in proportion to `c + 1`.
+        let deep = with(ChallengeDistribution::DeepWeighted);
+        let deep = column_counts(&deep, 500);
+        let per_weight = (500 * l0) as f64 / (k * (k + 1) / 2) as f64;
+        for (col, count) in deep.into_iter().enumerate() {
+            let expected = per_weight * (col + 1) as f64;
+            assert!((count as f64 - expected).abs() < 0.15 * expected);
+        }
+    }
+}
@@ -0,0 +1,50 @@
+//! Sources of time for enforcing the Verifier's response deadline.
+
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Reports the time elapsed since some fixed origin. Only differences between
+/// two readings of the same clock are meaningful.
+pub trait Clock: Debug + Send {
+    fn now(&self) -> Duration;
+}
+
+/// The system's wall clock. The Verifier only reads its clock when a deadline
+/// is set, as reading the system clock panics on targets without one (e.g.
+/// `wasm32-unknown-unknown`).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+    }
+}
+
+/// A clock that only moves when it is told to. Clones share the same time, so
+/// a caller can keep a clone and advance the clock owned by a Verifier.
+#[derive(Clone, Debug, Default)]
+pub struct ManualClock {
+    nanos: Arc<AtomicU64>,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        ManualClock::default()
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.nanos
+            .fetch_add(duration.as_nanos() as u64, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        Duration::from_nanos(self.nanos.load(Ordering::SeqCst))
+    }
+}
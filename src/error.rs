@@ -0,0 +1,113 @@
+//! The crate-wide error type. Each module reports its own failures with its
+//! own error type (e.g. `VerificationError`), and every public constructor and
+//! operation that can fail returns `pots::Result`, which wraps them.
+
+use std::fmt;
+use std::io;
+
+use crate::challenge::ChallengeError;
+use crate::merkle::MerkleError;
+use crate::params::ParamsError;
+use crate::receipt::ReceiptError;
+use crate::replica::ReplicaError;
+use crate::verifier::VerificationError;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The operating system's RNG is unavailable.
+    Rng(rand::Error),
+    Params(ParamsError),
+    /// Reading or writing persisted state failed.
+    Storage(io::Error),
+    /// A value could not be serialized or deserialized.
+    Encoding(bincode::Error),
+    /// A protocol message was invalid or arrived out of order.
+    Protocol(VerificationError),
+    Challenge(ChallengeError),
+    Merkle(MerkleError),
+    Replica(ReplicaError),
+    Receipt(ReceiptError),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Rng(e) => write!(f, "could not create RNG: {}", e),
+            Error::Params(e) => write!(f, "invalid parameters: {:?}", e),
+            Error::Storage(e) => write!(f, "storage error: {}", e),
+            Error::Encoding(e) => write!(f, "encoding error: {}", e),
+            Error::Protocol(e) => write!(f, "protocol error: {:?}", e),
+            Error::Challenge(e) => write!(f, "challenge error: {:?}", e),
+            Error::Merkle(e) => write!(f, "merkle error: {:?}", e),
+            Error::Replica(e) => write!(f, "replica error: {:?}", e),
+            Error::Receipt(e) => write!(f, "receipt error: {:?}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Rng(e) => Some(e),
+            Error::Storage(e) => Some(e),
+            Error::Encoding(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<rand::Error> for Error {
+    fn from(e: rand::Error) -> Self {
+        Error::Rng(e)
+    }
+}
+
+impl From<ParamsError> for Error {
+    fn from(e: ParamsError) -> Self {
+        Error::Params(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Storage(e)
+    }
+}
+
+impl From<bincode::Error> for Error {
+    fn from(e: bincode::Error) -> Self {
+        Error::Encoding(e)
+    }
+}
+
+impl From<VerificationError> for Error {
+    fn from(e: VerificationError) -> Self {
+        Error::Protocol(e)
+    }
+}
+
+impl From<ChallengeError> for Error {
+    fn from(e: ChallengeError) -> Self {
+        Error::Challenge(e)
+    }
+}
+
+impl From<MerkleError> for Error {
+    fn from(e: MerkleError) -> Self {
+        Error::Merkle(e)
+    }
+}
+
+impl From<ReplicaError> for Error {
+    fn from(e: ReplicaError) -> Self {
+        Error::Replica(e)
+    }
+}
+
+impl From<ReceiptError> for Error {
+    fn from(e: ReceiptError) -> Self {
+        Error::Receipt(e)
+    }
+}
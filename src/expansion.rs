@@ -0,0 +1,220 @@
+//! Measurements of how well a sampled bipartite graph expands.
+//!
+//! `Edges::new_permutation` assumes that a randomly sampled graph is a good
+//! expander. The functions here check that assumption for a given graph,
+//! either combinatorially (by sampling sets of sinks and counting their
+//! parents) or spectrally (by estimating the gap between the two largest
+//! singular values of the graph's degree-normalized biadjacency matrix).
+
+use rand::seq::index;
+use rand::Rng;
+
+use crate::graph::Edges;
+
+/// The number of power iterations used to estimate each singular value.
+const N_POWER_ITERATIONS: usize = 300;
+
+/// Samples `n_samples` random sets of `set_size` sinks and returns the
+/// smallest observed ratio of a set's number of parents to its size. An empty
+/// graph has no sets to sample and an expansion of zero.
+pub fn sampled_expansion<R: Rng>(
+    edges: &Edges,
+    set_size: usize,
+    n_samples: usize,
+    rng: &mut R,
+) -> f64 {
+    let n = edges.n();
+    if n == 0 {
+        return 0.0;
+    }
+    let set_size = set_size.min(n).max(1);
+    let mut min_expansion = f64::INFINITY;
+
+    for _ in 0..n_samples {
+        let mut in_set = vec![false; n];
+        for sink in index::sample(rng, n, set_size).into_iter() {
+            in_set[sink] = true;
+        }
+        let n_parents = (0..n)
+            .filter(|source| {
+                edges
+                    .get_children(*source)
+                    .iter()
+                    .any(|sink| in_set[*sink])
+            })
+            .count();
+        let expansion = n_parents as f64 / set_size as f64;
+        min_expansion = min_expansion.min(expansion);
+    }
+
+    min_expansion
+}
+
+/// Estimates the two largest singular values of the graph's degree-normalized
+/// biadjacency matrix `M = D_out^(-1/2) B D_in^(-1/2)` (where `B[source][sink]
+/// = 1` for each edge and `D_out` and `D_in` hold the sources' and sinks'
+/// degrees) using power iteration on `M^T M`. Normalizing by the degrees
+/// makes the largest singular value one for any graph with edges, so that a
+/// few high-degree sources cannot make the graph look well connected.
+pub fn singular_values<R: Rng>(edges: &Edges, rng: &mut R) -> (f64, f64) {
+    NormalizedBiadjacency::new(edges).singular_values(rng)
+}
+
+/// The normalized spectral gap `1 - sigma_2 / sigma_1` of the graph's
+/// degree-normalized biadjacency matrix. Larger gaps indicate better
+/// expansion; a disconnected graph, including one with a vertex without
+/// edges, has a gap of zero.
+pub fn spectral_gap<R: Rng>(edges: &Edges, rng: &mut R) -> f64 {
+    let matrix = NormalizedBiadjacency::new(edges);
+    if matrix.has_isolated_vertex() {
+        return 0.0;
+    }
+    let (sigma1, sigma2) = matrix.singular_values(rng);
+    if sigma1 == 0.0 {
+        0.0
+    } else {
+        1.0 - sigma2 / sigma1
+    }
+}
+
+/// The matrix `M = D_out^(-1/2) B D_in^(-1/2)`, stored as the graph's edges
+/// and the scale of each source and sink.
+struct NormalizedBiadjacency<'a> {
+    edges: &'a Edges,
+    /// `1 / sqrt(degree)` for each source, or zero if it has no edges.
+    source_scales: Vec<f64>,
+    /// `1 / sqrt(degree)` for each sink, or zero if it has no edges.
+    sink_scales: Vec<f64>,
+}
+
+impl<'a> NormalizedBiadjacency<'a> {
+    fn new(edges: &'a Edges) -> Self {
+        let n = edges.n();
+        let mut sink_degrees = vec![0; n];
+        for source in 0..n {
+            for sink in edges.get_children(source) {
+                if let Some(degree) = sink_degrees.get_mut(*sink) {
+                    *degree += 1;
+                }
+            }
+        }
+        let scale = |degree: usize| {
+            if degree == 0 {
+                0.0
+            } else {
+                1.0 / (degree as f64).sqrt()
+            }
+        };
+        NormalizedBiadjacency {
+            edges,
+            source_scales: (0..n)
+                .map(|source| scale(edges.get_children(source).len()))
+                .collect(),
+            sink_scales: sink_degrees.into_iter().map(scale).collect(),
+        }
+    }
+
+    fn has_isolated_vertex(&self) -> bool {
+        self.source_scales
+            .iter()
+            .chain(&self.sink_scales)
+            .any(|scale| *scale == 0.0)
+    }
+
+    fn singular_values<R: Rng>(&self, rng: &mut R) -> (f64, f64) {
+        let n = self.edges.n();
+        let random_vector = |rng: &mut R| -> Vec<f64> {
+            (0..n).map(|_| rng.gen::<f64>() - 0.5).collect()
+        };
+
+        let v1 = self.power_iteration(random_vector(rng), None);
+        let sigma1 = norm(&self.mul(&v1));
+
+        let v2 = self.power_iteration(random_vector(rng), Some(&v1));
+        let sigma2 = norm(&self.mul(&v2));
+
+        (sigma1, sigma2)
+    }
+
+    /// Runs power iteration on `M^T M` starting from `v`. If
+    /// `orthogonal_to` is given, `v` is kept orthogonal to it so that the
+    /// iteration converges to the next eigenvector.
+    fn power_iteration(
+        &self,
+        mut v: Vec<f64>,
+        orthogonal_to: Option<&[f64]>,
+    ) -> Vec<f64> {
+        for _ in 0..N_POWER_ITERATIONS {
+            if let Some(u) = orthogonal_to {
+                let projection = dot(&v, u);
+                for (vi, ui) in v.iter_mut().zip(u) {
+                    *vi -= projection * ui;
+                }
+            }
+            let v_norm = norm(&v);
+            if v_norm == 0.0 {
+                break;
+            }
+            for vi in v.iter_mut() {
+                *vi /= v_norm;
+            }
+            v = self.mul_transpose(&self.mul(&v));
+        }
+
+        if let Some(u) = orthogonal_to {
+            let projection = dot(&v, u);
+            for (vi, ui) in v.iter_mut().zip(u) {
+                *vi -= projection * ui;
+            }
+        }
+        let v_norm = norm(&v);
+        if v_norm != 0.0 {
+            for vi in v.iter_mut() {
+                *vi /= v_norm;
+            }
+        }
+        v
+    }
+
+    /// Computes `M v` for a vector `v` indexed by sink.
+    fn mul(&self, v: &[f64]) -> Vec<f64> {
+        (0..self.edges.n())
+            .map(|source| {
+                let sum: f64 = self
+                    .edges
+                    .get_children(source)
+                    .iter()
+                    .filter_map(|sink| {
+                        Some(v.get(*sink)? * self.sink_scales.get(*sink)?)
+                    })
+                    .sum();
+                sum * self.source_scales[source]
+            })
+            .collect()
+    }
+
+    /// Computes `M^T u` for a vector `u` indexed by source.
+    fn mul_transpose(&self, u: &[f64]) -> Vec<f64> {
+        let mut out = vec![0.0; self.edges.n()];
+        for (source, u_source) in u.iter().enumerate() {
+            let scaled = u_source * self.source_scales[source];
+            for sink in self.edges.get_children(source) {
+                if let Some(out_sink) = out.get_mut(*sink) {
+                    *out_sink += scaled;
+                }
+            }
+        }
+        for (out_sink, scale) in out.iter_mut().zip(&self.sink_scales) {
+            *out_sink *= scale;
+        }
+        out
+    }
+}
+
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(ai, bi)| ai * bi).sum()
+}
+
+fn norm(v: &[f64]) -> f64 {
+    dot(v, v).sqrt()
+}
@@ -1,44 +1,389 @@
-//! An implementation of a stacked bipartite expander DAG.
+//! The DAGs whose vertices the Prover labels.
+//!
+//! Every graph family arranges its `n * k` vertices in `k` columns of `n`
+//! vertices. A vertex's parents are either in the previous column or earlier
+//! in its own column, so labeling the vertices in column order always labels
+//! a vertex's parents before the vertex itself.
 
 use rand::rngs::OsRng;
 use rand::seq::SliceRandom;
+use rand::{CryptoRng, Rng, RngCore};
+use serde::{Deserialize, Serialize};
 
+use crate::challenge::ChallengeRng;
+use crate::error::{Error, Result};
+use crate::expansion;
 use crate::hasher::Hasher;
+use crate::params::{ParamsError, ProtoParams};
 
 pub const IN_DEGREE: usize = 16;
 
+/// The number of graphs sampled before giving up on reaching the parameters'
+/// minimum spectral gap.
+const MAX_SAMPLING_ATTEMPTS: usize = 20;
+
+/// The number of random swaps tried to move a sink off a source it is already
+/// connected to before falling back to an augmenting-path search.
+const MAX_SWAP_ATTEMPTS: usize = 64;
+
 pub type VertexLabel = Vec<u8>;
 
-/// A mapping from each source in a bipartite expander to its corresponding
-/// sinks.
-#[derive(Clone, Debug)]
-pub struct Edges(Vec<Vec<usize>>);
+/// The construction used for the Prover's graph.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub enum GraphFamily {
+    /// A random bipartite expander stacked `k` times.
+    #[default]
+    StackedExpander,
+    /// The stacked expander overlaid with a depth-robust DRSample graph
+    /// within each column.
+    DrSample,
+    /// Butterfly networks stacked back to back; `n` must be a power of two.
+    /// Their challenge size is only a spot-check bound, as they have no
+    /// pebbling analysis.
+    Butterfly,
+}
+
+/// The dependencies between the vertices within a column.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub enum LabelingMode {
+    /// Vertices depend only on the parents given by the graph family, so
+    /// in the stacked expander a whole column can be labeled in parallel.
+    #[default]
+    Parallel,
+    /// Each vertex also depends on the previous vertex in its own column, so
+    /// a column must be labeled sequentially. Proofs still open only the
+    /// challenged vertex, as the Verifier recomputes its in-column
+    /// predecessors while pebbling.
+    Localized,
+}
+
+/// The edges of a graph in one of the `GraphFamily`s.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Edges {
+    family: GraphFamily,
+    labeling: LabelingMode,
+    n: usize,
+    /// A mapping from each source in the bipartite expander to its
+    /// corresponding sinks (empty for butterfly graphs).
+    expander: Vec<Vec<usize>>,
+    /// For DRSample overlays, the randomly sampled in-column parent of each
+    /// vertex (in addition to its predecessor).
+    drg: Vec<usize>,
+}
 
 impl Edges {
-    pub fn new_permutation(n: usize) -> Self {
-        let mut rng = OsRng::new().expect("could not create OsRng");
-        let mut indices: Vec<usize> = (0..n).collect();
-        let mut edges: Vec<Vec<usize>> = vec![vec![]; n];
-
-        for sink_index in 0..n {
-            indices.shuffle(&mut rng);
-            for source_index in &indices[..IN_DEGREE] {
-                edges[*source_index].push(sink_index);
+    pub fn new(family: GraphFamily, n: usize) -> Result<Self> {
+        let mut rng = OsRng::new()?;
+        Edges::new_with_rng(family, n, &mut rng)
+    }
+
+    /// Samples the edges of a graph for `params`, labeled in the parameters'
+    /// `LabelingMode` and (if the parameters set one) expanding with at least
+    /// their minimum spectral gap.
+    pub fn from_params(params: &ProtoParams) -> Result<Self> {
+        let mut rng = OsRng::new()?;
+        Edges::from_params_with_rng(params, &mut rng)
+    }
+
+    /// Like `from_params`, but samples the edges using `rng`.
+    pub fn from_params_with_rng<R>(
+        params: &ProtoParams,
+        rng: &mut R,
+    ) -> Result<Self>
+    where
+        R: RngCore + CryptoRng,
+    {
+        let edges = match params.min_spectral_gap {
+            Some(min_spectral_gap)
+                if params.family != GraphFamily::Butterfly =>
+            {
+                Edges::new_checked(
+                    params.family,
+                    params.n,
+                    rng,
+                    min_spectral_gap,
+                    MAX_SAMPLING_ATTEMPTS,
+                )?
+                .ok_or(ParamsError::InsufficientExpansion {
+                    min_spectral_gap,
+                    n_attempts: MAX_SAMPLING_ATTEMPTS,
+                })?
+            }
+            _ => Edges::new_with_rng(params.family, params.n, rng)?,
+        };
+        Ok(edges.with_labeling(params.labeling))
+    }
+
+    /// Samples the edges of a graph in `family` using `rng`; seeding `rng`
+    /// makes the sampled graph reproducible.
+    pub fn new_with_rng<R>(
+        family: GraphFamily,
+        n: usize,
+        rng: &mut R,
+    ) -> Result<Self>
+    where
+        R: RngCore + CryptoRng,
+    {
+        let edges = match family {
+            GraphFamily::StackedExpander => {
+                Edges::new_permutation_with_rng(n, rng)?
+            }
+            GraphFamily::DrSample => Edges {
+                family,
+                drg: sample_drg(n, rng),
+                ..Edges::new_permutation_with_rng(n, rng)?
+            },
+            GraphFamily::Butterfly => {
+                if n < 2 {
+                    return Err(Error::Params(ParamsError::TooFewVertices {
+                        n,
+                        min_n: 2,
+                    }));
+                }
+                if !n.is_power_of_two() {
+                    return Err(Error::Params(ParamsError::NotPowerOfTwo {
+                        n,
+                    }));
+                }
+                Edges {
+                    family,
+                    labeling: LabelingMode::default(),
+                    n,
+                    expander: vec![],
+                    drg: vec![],
+                }
+            }
+        };
+        Ok(edges)
+    }
+
+    pub fn new_permutation(n: usize) -> Result<Self> {
+        let mut rng = OsRng::new()?;
+        Edges::new_permutation_with_rng(n, &mut rng)
+    }
+
+    /// Samples the expander's edges using `rng`; seeding `rng` makes the
+    /// sampled graph reproducible. The expander is the union of `IN_DEGREE`
+    /// random perfect matchings between the sources and the sinks, so every
+    /// source has `IN_DEGREE` sinks just as every sink has `IN_DEGREE`
+    /// sources.
+    pub fn new_permutation_with_rng<R>(n: usize, rng: &mut R) -> Result<Self>
+    where
+        R: RngCore + CryptoRng,
+    {
+        if n < IN_DEGREE {
+            return Err(Error::Params(ParamsError::TooFewVertices {
+                n,
+                min_n: IN_DEGREE,
+            }));
+        }
+        let mut parents: Vec<Vec<usize>> =
+            vec![Vec::with_capacity(IN_DEGREE); n];
+        for _ in 0..IN_DEGREE {
+            let matching = sample_matching(&parents, rng);
+            for (sink_parents, source) in parents.iter_mut().zip(matching) {
+                sink_parents.push(source);
             }
         }
 
-        for edges_from_source in edges.iter_mut() {
-            edges_from_source.sort();
+        // Sinks are visited in order, so each source's sinks are sorted.
+        let mut edges: Vec<Vec<usize>> = vec![Vec::with_capacity(IN_DEGREE); n];
+        for (sink, sink_parents) in parents.iter().enumerate() {
+            for &source in sink_parents {
+                edges[source].push(sink);
+            }
         }
 
-        Edges(edges)
+        Ok(Edges {
+            family: GraphFamily::StackedExpander,
+            labeling: LabelingMode::default(),
+            n,
+            expander: edges,
+            drg: vec![],
+        })
+    }
+
+    /// Samples edges with `rng` until the graph's spectral gap (see
+    /// `expansion::spectral_gap`) is at least `min_spectral_gap`. Returns
+    /// `None` if none of `max_attempts` sampled graphs reached the threshold.
+    pub fn new_permutation_checked<R>(
+        n: usize,
+        rng: &mut R,
+        min_spectral_gap: f64,
+        max_attempts: usize,
+    ) -> Result<Option<Self>>
+    where
+        R: RngCore + CryptoRng,
+    {
+        Edges::new_checked(
+            GraphFamily::StackedExpander,
+            n,
+            rng,
+            min_spectral_gap,
+            max_attempts,
+        )
+    }
+
+    /// Like `new_permutation_checked`, for a graph in any family with an
+    /// expander.
+    fn new_checked<R>(
+        family: GraphFamily,
+        n: usize,
+        rng: &mut R,
+        min_spectral_gap: f64,
+        max_attempts: usize,
+    ) -> Result<Option<Self>>
+    where
+        R: RngCore + CryptoRng,
+    {
+        for _ in 0..max_attempts {
+            let edges = Edges::new_with_rng(family, n, rng)?;
+            if edges.spectral_gap() >= min_spectral_gap {
+                return Ok(Some(edges));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Sets whether each vertex also depends on the previous vertex in its
+    /// column.
+    pub fn with_labeling(mut self, labeling: LabelingMode) -> Self {
+        self.labeling = labeling;
+        self
+    }
+
+    pub fn family(&self) -> GraphFamily {
+        self.family
+    }
+
+    /// The expander's spectral gap (see `expansion::spectral_gap`),
+    /// estimated from a fixed starting point so that the Prover and the
+    /// Verifier agree on it.
+    pub fn spectral_gap(&self) -> f64 {
+        expansion::spectral_gap(self, &mut ChallengeRng::new(&[]))
+    }
+
+    /// Checks that the edges are those of a graph in their family. Edges
+    /// received from the Prover (or deserialized from storage) must be
+    /// validated before they are labeled, as labeling malformed edges could
+    /// index past a column.
+    pub fn validate(&self) -> Result<()> {
+        let n = self.n;
+        let malformed =
+            |vertex| Err(Error::Params(ParamsError::MalformedEdges { vertex }));
+        match self.family {
+            GraphFamily::StackedExpander | GraphFamily::DrSample => {
+                if n < IN_DEGREE {
+                    return Err(Error::Params(ParamsError::TooFewVertices {
+                        n,
+                        min_n: IN_DEGREE,
+                    }));
+                }
+                if self.expander.len() != n {
+                    return malformed(self.expander.len().min(n));
+                }
+                let mut in_degrees = vec![0; n];
+                // Every source and every sink has `IN_DEGREE` edges, so no
+                // source is left out of the graph and none dominates it.
+                for (source, sinks) in self.expander.iter().enumerate() {
+                    let sorted = sinks.windows(2).all(|pair| pair[0] < pair[1]);
+                    if !sorted
+                        || sinks.len() != IN_DEGREE
+                        || sinks.last().is_some_and(|&sink| sink >= n)
+                    {
+                        return malformed(source);
+                    }
+                    for &sink in sinks {
+                        in_degrees[sink] += 1;
+                    }
+                }
+                if let Some(sink) =
+                    in_degrees.iter().position(|&degree| degree != IN_DEGREE)
+                {
+                    return malformed(sink);
+                }
+            }
+            GraphFamily::Butterfly => {
+                if n < 2 {
+                    return Err(Error::Params(ParamsError::TooFewVertices {
+                        n,
+                        min_n: 2,
+                    }));
+                }
+                if !n.is_power_of_two() {
+                    return Err(Error::Params(ParamsError::NotPowerOfTwo {
+                        n,
+                    }));
+                }
+                if !self.expander.is_empty() {
+                    return malformed(0);
+                }
+            }
+        }
+        if self.family == GraphFamily::DrSample {
+            if self.drg.len() != n {
+                return malformed(self.drg.len().min(n));
+            }
+            // Each vertex `v > 1` has a parent at least two vertices back.
+            let invalid_parent = self
+                .drg
+                .iter()
+                .enumerate()
+                .skip(2)
+                .position(|(vertex, &parent)| parent + 2 > vertex);
+            if let Some(position) = invalid_parent {
+                return malformed(position + 2);
+            }
+        } else if !self.drg.is_empty() {
+            return malformed(0);
+        }
+        Ok(())
+    }
+
+    pub fn labeling(&self) -> LabelingMode {
+        self.labeling
+    }
+
+    /// Returns the parents of the vertex at `(col, vertex)` as `(col,
+    /// vertex)` pairs. A vertex without parents is a source.
+    pub fn parents(&self, col: usize, vertex: usize) -> Vec<(usize, usize)> {
+        let mut parents = vec![];
+        match self.family {
+            GraphFamily::StackedExpander | GraphFamily::DrSample => {
+                if col > 0 {
+                    for parent in self.get_parents(vertex) {
+                        parents.push((col - 1, parent));
+                    }
+                }
+            }
+            GraphFamily::Butterfly => {
+                if col > 0 {
+                    let n_stages = (self.n.trailing_zeros() as usize).max(1);
+                    let stage = (col - 1) % n_stages;
+                    parents.push((col - 1, vertex));
+                    parents.push((col - 1, vertex ^ (1 << stage)));
+                }
+            }
+        }
+        let is_localized = self.family == GraphFamily::DrSample
+            || self.labeling == LabelingMode::Localized;
+        if is_localized && vertex > 0 {
+            parents.push((col, vertex - 1));
+        }
+        if self.family == GraphFamily::DrSample && vertex > 1 {
+            if let Some(&parent) = self.drg.get(vertex) {
+                parents.push((col, parent));
+            }
+        }
+        parents
     }
 
-    /// Returns the source indices that the sink index `vertex` is connected to.
+    /// Returns the source indices that the sink index `vertex` is connected to
+    /// in the bipartite expander.
     pub fn get_parents(&self, vertex: usize) -> Vec<usize> {
         let mut parents = vec![];
         let mut n_parents = 0;
-        for (source_index, edges) in self.0.iter().enumerate() {
+        for (source_index, edges) in self.expander.iter().enumerate() {
             if edges.contains(&vertex) {
                 parents.push(source_index);
                 n_parents += 1;
@@ -50,39 +395,302 @@ impl Edges {
         parents
     }
 
-    fn n(&self) -> usize {
-        self.0.len()
+    /// Returns the sink indices that the source index `vertex` is connected
+    /// to in the bipartite expander.
+    pub fn get_children(&self, vertex: usize) -> &[usize] {
+        self.expander.get(vertex).map_or(&[], |sinks| sinks.as_slice())
+    }
+
+    pub fn n(&self) -> usize {
+        self.n
+    }
+}
+
+/// Samples a random perfect matching between sources and sinks
+/// (`matching[sink]` is the sink's source) that avoids the edges in
+/// `parents`, which must be the union of fewer than `n` perfect matchings.
+fn sample_matching<R: Rng>(parents: &[Vec<usize>], rng: &mut R) -> Vec<usize> {
+    let n = parents.len();
+    let mut matching: Vec<usize> = (0..n).collect();
+    matching.shuffle(rng);
+
+    let mut is_matched = vec![true; n];
+    for sink in 0..n {
+        if !parents[sink].contains(&matching[sink]) {
+            continue;
+        }
+        // Trade sources with a random sink that can take this sink's source.
+        let swapped = (0..MAX_SWAP_ATTEMPTS).any(|_| {
+            let other = rng.gen_range(0, n);
+            let can_swap = !parents[sink].contains(&matching[other])
+                && !parents[other].contains(&matching[sink]);
+            if can_swap {
+                matching.swap(sink, other);
+            }
+            can_swap
+        });
+        is_matched[sink] = swapped;
+    }
+
+    if is_matched.iter().any(|matched| !matched) {
+        complete_matching(parents, &mut matching, &is_matched);
+    }
+    matching
+}
+
+/// Matches every sink that `is_matched` marks as unmatched along augmenting
+/// paths. Sinks whose parents are the union of `r < n` perfect matchings can
+/// be matched to any of `n - r` sources, so a perfect matching always exists.
+fn complete_matching(
+    parents: &[Vec<usize>],
+    matching: &mut [usize],
+    is_matched: &[bool],
+) {
+    let n = parents.len();
+    let mut sink_of = vec![None; n];
+    for sink in (0..n).filter(|sink| is_matched[*sink]) {
+        sink_of[matching[sink]] = Some(sink);
+    }
+    for sink in (0..n).filter(|sink| !is_matched[*sink]) {
+        let mut visited = vec![false; n];
+        let found =
+            augment(parents, matching, &mut sink_of, &mut visited, sink);
+        debug_assert!(found, "regular bipartite graphs have perfect matchings");
+    }
+}
+
+/// Searches for an augmenting path from `sink`, rematching the sinks along it.
+fn augment(
+    parents: &[Vec<usize>],
+    matching: &mut [usize],
+    sink_of: &mut [Option<usize>],
+    visited: &mut [bool],
+    sink: usize,
+) -> bool {
+    for source in 0..parents.len() {
+        if visited[source] || parents[sink].contains(&source) {
+            continue;
+        }
+        visited[source] = true;
+        let rematched = match sink_of[source] {
+            None => true,
+            Some(other) => augment(parents, matching, sink_of, visited, other),
+        };
+        if rematched {
+            matching[sink] = source;
+            sink_of[source] = Some(sink);
+            return true;
+        }
+    }
+    false
+}
+
+/// Samples the DRSample parent `v - r` of each vertex `v > 1`, where `r` is
+/// drawn from a randomly chosen power-of-two sized bucket of distances.
+fn sample_drg<R: RngCore>(n: usize, rng: &mut R) -> Vec<usize> {
+    let mut drg = vec![0; n];
+    for (vertex, parent) in drg.iter_mut().enumerate().skip(2) {
+        let log2_vertex = (usize::BITS - 1 - vertex.leading_zeros()) as usize;
+        let bucket = rng.gen_range(1, log2_vertex + 2);
+        let max_dist = vertex.min(1 << bucket);
+        let min_dist = (max_dist / 2).max(2);
+        *parent = vertex - rng.gen_range(min_dist, max_dist + 1);
+    }
+    drg
+}
+
+/// Labels the vertex at `(col, vertex)`, looking up the labels of its parents
+/// with `label_of`.
+pub(crate) fn label_vertex<'a>(
+    edges: &Edges,
+    nonce: &[u8],
+    hasher: &mut Hasher,
+    col: usize,
+    vertex: usize,
+    label_of: impl Fn(usize, usize) -> &'a VertexLabel,
+) -> VertexLabel {
+    let parents = edges.parents(col, vertex);
+    if parents.is_empty() {
+        return hasher.label_source(nonce, vertex);
     }
+    let parent_labels: Vec<&VertexLabel> = parents
+        .iter()
+        .map(|(parent_col, parent)| label_of(*parent_col, *parent))
+        .collect();
+    hasher.label_non_source(&parent_labels)
 }
 
 /// A labeled graph.
-#[derive(Debug)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct LabelMatrix(pub Vec<Vec<VertexLabel>>);
 
 impl LabelMatrix {
-    pub fn new(edges: &Edges, k: usize, nonce: &[u8]) -> Self {
+    /// Labels every vertex of the graph for the Prover `prover_id`.
+    pub fn new(
+        edges: &Edges,
+        k: usize,
+        nonce: &[u8],
+        prover_id: &[u8],
+    ) -> Self {
+        let mut label_matrix = LabelMatrix::empty(k);
+        let mut hasher = Hasher::with_prover_id(prover_id);
+        for _ in 0..(edges.n() * k) {
+            label_matrix.label_next(edges, nonce, &mut hasher);
+        }
+        label_matrix
+    }
+
+    /// Creates a matrix with `k` columns in which no vertex is labeled.
+    pub fn empty(k: usize) -> Self {
+        LabelMatrix(vec![vec![]; k])
+    }
+
+    /// Labels the first unlabeled vertex (in column order) and returns its
+    /// label.
+    pub fn label_next(
+        &mut self,
+        edges: &Edges,
+        nonce: &[u8],
+        hasher: &mut Hasher,
+    ) -> &VertexLabel {
         let n = edges.n();
-        let mut label_matrix: Vec<Vec<VertexLabel>> = vec![vec![]; k];
-        let mut hasher = Hasher::new();
+        let index = self.n_labels();
+        let (col, vertex) = (index / n, index % n);
 
-        label_matrix[0] = (0..n)
-            .map(|i| hasher.label_source(&nonce, i))
-            .collect();
+        let labels = &self.0;
+        let vertex_label =
+            label_vertex(edges, nonce, hasher, col, vertex, |col, vertex| {
+                &labels[col][vertex]
+            });
 
-        for col in 1..k {
-            for vertex in 0..n {
-                let parent_labels: Vec<&VertexLabel> = edges
-                    .get_parents(vertex)
-                    .iter()
-                    .map(|parent_index| &label_matrix[col - 1][*parent_index])
-                    .collect();
+        self.0[col].push(vertex_label);
+        &self.0[col][vertex]
+    }
 
-                let vertex_label = hasher.label_non_source(&parent_labels);
-                label_matrix[col].push(vertex_label);
-            }
+    /// The total number of labels in the matrix.
+    pub fn n_labels(&self) -> usize {
+        self.0.iter().map(|col_labels| col_labels.len()).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::params::Space;
+    use crate::verifier::Verifier;
+
+    fn sample(family: GraphFamily, n: usize) -> Edges {
+        let mut rng = StdRng::seed_from_u64(0);
+        Edges::new_with_rng(family, n, &mut rng).unwrap()
+    }
+
+    fn rejects(edges: &Edges) -> bool {
+        matches!(edges.validate(), Err(Error::Params(_)))
+    }
+
+    #[test]
+    fn sampled_edges_are_valid() {
+        for family in &[
+            GraphFamily::StackedExpander,
+            GraphFamily::DrSample,
+            GraphFamily::Butterfly,
+        ] {
+            assert!(sample(*family, 32).validate().is_ok());
+        }
+        // Dense expanders are still regular, down to the complete graph.
+        for n in &[IN_DEGREE, IN_DEGREE + 1, IN_DEGREE + 5] {
+            let edges = sample(GraphFamily::StackedExpander, *n);
+            assert!(edges.validate().is_ok());
         }
+        let mut rng = StdRng::seed_from_u64(0);
+        assert!(Edges::new_with_rng(GraphFamily::Butterfly, 1, &mut rng)
+            .is_err());
+    }
+
+    #[test]
+    fn malformed_edges_are_rejected() {
+        let expander = sample(GraphFamily::StackedExpander, 32);
 
-        LabelMatrix(label_matrix)
+        let mut edges = expander.clone();
+        edges.expander.clear();
+        assert!(rejects(&edges));
+
+        let mut edges = expander.clone();
+        edges.expander[3] = vec![32];
+        assert!(rejects(&edges));
+
+        let mut edges = expander.clone();
+        edges.expander[3].pop();
+        assert!(rejects(&edges));
+
+        let mut edges = expander;
+        edges.drg = vec![0; 32];
+        assert!(rejects(&edges));
+
+        let drsample = sample(GraphFamily::DrSample, 32);
+        let mut edges = drsample.clone();
+        edges.drg.clear();
+        assert!(rejects(&edges));
+        // Unvalidated edges must not panic when their parents are listed.
+        assert_eq!(edges.parents(1, 5).len(), IN_DEGREE + 1);
+
+        let mut edges = drsample;
+        edges.drg[5] = 4;
+        assert!(rejects(&edges));
+
+        let mut edges = sample(GraphFamily::Butterfly, 32);
+        edges.n = 1;
+        assert!(rejects(&edges));
+        assert_eq!(edges.parents(1, 0).len(), 2);
     }
-}
 
+    #[test]
+    fn degenerate_expanders_are_rejected() {
+        // Every sink's parents are the first `IN_DEGREE` sources, so every
+        // label in a column after the first is the same.
+        let n = 32;
+        let mut edges = sample(GraphFamily::StackedExpander, n);
+        edges.expander = (0..n)
+            .map(|source| {
+                if source < IN_DEGREE {
+                    (0..n).collect()
+                } else {
+                    vec![]
+                }
+            })
+            .collect();
+        assert_eq!(
+            edges.validate().unwrap_err().to_string(),
+            "invalid parameters: MalformedEdges { vertex: 0 }"
+        );
+        assert_eq!(edges.spectral_gap(), 0.0);
+        assert!(sample(GraphFamily::StackedExpander, n).spectral_gap() > 0.1);
+
+        let params = ProtoParams::new(Space::Kbs(8)).unwrap();
+        assert_eq!(params.n, n);
+        let mut verifier = Verifier::new(params, vec![], vec![]).unwrap();
+        assert!(verifier.set_graph_description(edges, vec![0; 32]).is_err());
+    }
+
+    #[test]
+    fn sampling_enforces_the_minimum_spectral_gap() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let params = ProtoParams::new(Space::Kbs(8)).unwrap();
+        let edges = Edges::from_params_with_rng(
+            &params.clone().with_min_spectral_gap(0.1),
+            &mut rng,
+        )
+        .unwrap();
+        assert!(edges.spectral_gap() >= 0.1);
+
+        // The gap is at most one, so no graph reaches this threshold.
+        let params = params.with_min_spectral_gap(1.5);
+        assert!(matches!(
+            Edges::from_params_with_rng(&params, &mut rng),
+            Err(Error::Params(ParamsError::InsufficientExpansion { .. }))
+        ));
+    }
+}
@@ -1,28 +1,74 @@
 //! A wrapper around SHA3-256, the hash function used to label graph vertices
 //! and Merkle nodes.
 
+use std::io::{self, Write};
+
+use serde::Serialize;
 use sha3::{Digest, Sha3_256};
 
+use crate::error::Result;
 use crate::graph::VertexLabel;
-use crate::merkle::MerkleLabel;
+use crate::merkle::{MerkleLabel, MerkleProof};
 
 pub const DIGEST_LENGTH: usize = 256 / 8;
 
-#[derive(Debug)]
-pub struct Hasher(Sha3_256);
+/// Prefixes every vertex label's input, so that a vertex label can never be
+/// mistaken for the label of an internal Merkle node (or vice versa).
+const VERTEX_LABEL_TAG: u8 = 0;
+/// Prefixes every internal Merkle node's input.
+const MERKLE_NODE_TAG: u8 = 1;
+
+/// A hasher whose vertex labels are keyed by the identity (e.g. the public
+/// key bytes) of the Prover that computes them, so that labels computed for
+/// one Prover are useless to another.
+#[derive(Debug, Default)]
+pub struct Hasher {
+    sha3: Sha3_256,
+    prover_id: Vec<u8>,
+}
+
+/// Streams serialized bytes into SHA3 rather than buffering them.
+struct Sha3Writer<'a>(&'a mut Sha3_256);
+
+impl Write for Sha3Writer<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.input(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
 
 impl Hasher {
     pub fn new() -> Self {
-        Hasher(Sha3_256::new())
+        Hasher::default()
+    }
+
+    /// Creates a hasher that mixes `prover_id` into every vertex label.
+    pub fn with_prover_id(prover_id: &[u8]) -> Self {
+        Hasher {
+            sha3: Sha3_256::new(),
+            prover_id: prover_id.to_vec(),
+        }
     }
 
     pub fn digest(&mut self) -> Vec<u8> {
-        self.0.result_reset().to_vec()
+        self.sha3.result_reset().to_vec()
+    }
+
+    /// Inputs the length-prefixed prover identity.
+    fn input_prover_id(&mut self) {
+        self.sha3.input((self.prover_id.len() as u64).to_be_bytes());
+        self.sha3.input(&self.prover_id);
     }
 
     pub fn label_source(&mut self, nonce: &[u8], i: usize) -> VertexLabel {
-        self.0.input(nonce);
-        self.0.input(&i.to_be_bytes());
+        self.sha3.input([VERTEX_LABEL_TAG]);
+        self.input_prover_id();
+        self.sha3.input(nonce);
+        self.sha3.input(i.to_be_bytes());
         self.digest()
     }
 
@@ -30,19 +76,104 @@ impl Hasher {
         &mut self,
         parent_labels: &[&VertexLabel],
     ) -> VertexLabel {
+        self.sha3.input([VERTEX_LABEL_TAG]);
+        self.input_prover_id();
         for parent_label in parent_labels {
-            self.0.input(parent_label);
+            self.sha3.input(parent_label);
         }
         self.digest()
     }
 
     pub fn label_merkle_node(
         &mut self,
-        left_input: &MerkleLabel,
-        right_input: &MerkleLabel,
+        inputs: &[&MerkleLabel],
     ) -> MerkleLabel {
-        self.0.input(left_input);
-        self.0.input(right_input);
+        self.sha3.input([MERKLE_NODE_TAG]);
+        for input in inputs {
+            self.sha3.input(input);
+        }
+        self.digest()
+    }
+
+    /// Hashes the serialization of the Prover's commitment (its parameters,
+    /// nonce, identity, graph and Merkle root), so that a challenge seeded
+    /// with the digest depends on every part of the commitment.
+    pub fn commitment_digest<T: Serialize + ?Sized>(
+        &mut self,
+        commitment: &T,
+    ) -> Result<Vec<u8>> {
+        bincode::serialize_into(Sha3Writer(&mut self.sha3), commitment)?;
+        Ok(self.digest())
+    }
+
+    /// Seeds a challenge from a commitment digest and public randomness.
+    pub fn challenge_seed(
+        &mut self,
+        commitment_digest: &[u8],
+        randomness: &[u8],
+    ) -> Vec<u8> {
+        self.sha3.input(commitment_digest);
+        self.sha3.input((randomness.len() as u64).to_be_bytes());
+        self.sha3.input(randomness);
+        self.digest()
+    }
+
+    /// Tags a challenge issued for the commitment with `commitment_digest`
+    /// under `key`, a secret known only to the Verifier. SHA3 is not
+    /// vulnerable to length extension, so the keyed hash is a MAC.
+    pub fn challenge_tag(
+        &mut self,
+        key: &[u8],
+        commitment_digest: &[u8],
+        indices: &[usize],
+    ) -> Vec<u8> {
+        self.sha3.input((key.len() as u64).to_be_bytes());
+        self.sha3.input(key);
+        self.sha3.input(commitment_digest);
+        for index in indices {
+            self.sha3.input((*index as u64).to_be_bytes());
+        }
+        self.digest()
+    }
+
+    /// The `counter`th block of output of the challenge RNG seeded with
+    /// `seed`.
+    pub fn challenge_block(&mut self, seed: &[u8], counter: u64) -> Vec<u8> {
+        self.sha3.input(seed);
+        self.sha3.input(counter.to_be_bytes());
+        self.digest()
+    }
+
+    /// Seeds the challenge for `epoch` from the digest of the previous
+    /// epoch's proof, the Prover's Merkle root and the beacon's randomness
+    /// for the epoch.
+    pub fn epoch_seed(
+        &mut self,
+        prev_digest: &[u8],
+        epoch: u64,
+        merkle_root: &MerkleLabel,
+        randomness: &[u8],
+    ) -> Vec<u8> {
+        self.sha3.input((prev_digest.len() as u64).to_be_bytes());
+        self.sha3.input(prev_digest);
+        self.sha3.input(epoch.to_be_bytes());
+        self.sha3.input(merkle_root);
+        self.sha3.input(randomness);
+        self.digest()
+    }
+
+    pub fn epoch_proof_digest(
+        &mut self,
+        epoch: u64,
+        proofs: &[MerkleProof],
+    ) -> Vec<u8> {
+        self.sha3.input(epoch.to_be_bytes());
+        for proof in proofs {
+            self.sha3.input((proof.challenge_index as u64).to_be_bytes());
+            for node in &proof.path {
+                self.sha3.input(node);
+            }
+        }
         self.digest()
     }
 }
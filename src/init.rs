@@ -0,0 +1,258 @@
+//! Incremental initialization of a Prover.
+//!
+//! `Prover::new` labels the whole graph and builds the Merkle tree in a single
+//! blocking call. An `Initializer` does the same work a bounded number of
+//! vertices at a time, so that callers (e.g. a browser's event loop) can report
+//! progress between steps, stop early, or serialize the partially initialized
+//! state and resume from it later.
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Result;
+use crate::graph::{Edges, LabelMatrix};
+use crate::hasher::Hasher;
+use crate::merkle::{MerkleError, MerkleTreeBuilder};
+use crate::params::ProtoParams;
+use crate::prover::{self, Prover};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Initializer {
+    params: ProtoParams,
+    nonce: Vec<u8>,
+    prover_id: Vec<u8>,
+    edges: Edges,
+    label_matrix: LabelMatrix,
+    merkle_tree: MerkleTreeBuilder,
+    cancelled: bool,
+}
+
+impl Initializer {
+    pub fn new(
+        params: ProtoParams,
+        nonce: Vec<u8>,
+        prover_id: Vec<u8>,
+    ) -> Result<Self> {
+        Initializer::with_cached_layers(params, nonce, prover_id, usize::MAX)
+    }
+
+    /// Creates an Initializer for a Prover that keeps only the top
+    /// `n_cached_layers` layers of its Merkle tree in memory.
+    pub fn with_cached_layers(
+        params: ProtoParams,
+        nonce: Vec<u8>,
+        prover_id: Vec<u8>,
+        n_cached_layers: usize,
+    ) -> Result<Self> {
+        let edges = Edges::from_params(&params)?;
+        Initializer::from_edges(
+            params,
+            nonce,
+            prover_id,
+            edges,
+            n_cached_layers,
+        )
+    }
+
+    /// Creates an Initializer for a graph whose edges were sampled by the
+    /// caller.
+    pub fn from_edges(
+        params: ProtoParams,
+        nonce: Vec<u8>,
+        prover_id: Vec<u8>,
+        edges: Edges,
+        n_cached_layers: usize,
+    ) -> Result<Self> {
+        prover::check_graph(&params, &edges)?;
+        let label_matrix = LabelMatrix::empty(params.k);
+        let merkle_tree = MerkleTreeBuilder::new(
+            params.n * params.k,
+            params.arity,
+            n_cached_layers,
+        );
+        Ok(Initializer {
+            params,
+            nonce,
+            prover_id,
+            edges,
+            label_matrix,
+            merkle_tree,
+            cancelled: false,
+        })
+    }
+
+    /// Labels up to `max_vertices` more vertices, adding each label to the
+    /// Merkle tree as it is computed. Once every vertex is labeled, each step
+    /// adds up to `max_vertices` of the dataless leaves that pad the tree.
+    /// Returns the fraction of the initialization completed so far.
+    pub fn step(&mut self, max_vertices: usize) -> f32 {
+        let n_labels = self.params.n * self.params.k;
+        let mut hasher = Hasher::with_prover_id(&self.prover_id);
+
+        for _ in 0..max_vertices {
+            if self.cancelled || self.is_done() {
+                break;
+            }
+            if self.merkle_tree.n_pushed_leaves() < n_labels {
+                let label = self
+                    .label_matrix
+                    .label_next(&self.edges, &self.nonce, &mut hasher)
+                    .clone();
+                self.merkle_tree.push_leaf(label);
+            } else {
+                self.merkle_tree.push_padding_leaf();
+            }
+        }
+
+        self.progress()
+    }
+
+    /// The fraction of the initialization completed so far.
+    pub fn progress(&self) -> f32 {
+        let n_done = self.merkle_tree.n_pushed_leaves() as f32;
+        let n_total = self.merkle_tree.n_padded_leaves() as f32;
+        n_done / n_total
+    }
+
+    pub fn is_done(&self) -> bool {
+        !self.cancelled
+            && self.merkle_tree.n_pushed_leaves()
+                == self.merkle_tree.n_padded_leaves()
+    }
+
+    /// Stops the initialization and frees the labels computed so far. A
+    /// cancelled Initializer never completes.
+    pub fn cancel(&mut self) {
+        self.cancelled = true;
+        self.label_matrix = LabelMatrix::empty(0);
+        self.merkle_tree = MerkleTreeBuilder::new(0, self.params.arity, 0);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled
+    }
+
+    /// Checks that a deserialized Initializer is consistent with its
+    /// parameters. State restored from untrusted storage must be validated
+    /// before it is stepped or finished.
+    pub fn validate(&self) -> Result<()> {
+        prover::check_graph(&self.params, &self.edges)?;
+        let (n, k) = (self.params.n, self.params.k);
+        let n_leaves = match (self.cancelled, n.checked_mul(k)) {
+            (true, _) => 0,
+            (false, Some(n_leaves)) => n_leaves,
+            (false, None) => return Err(MerkleError::MalformedTree.into()),
+        };
+        self.merkle_tree.check(n_leaves, self.params.arity)?;
+
+        // The labels are computed in column order, one per pushed leaf.
+        let n_labels = self.merkle_tree.n_pushed_leaves().min(n_leaves);
+        let n_cols = if self.cancelled { 0 } else { k };
+        let columns = &self.label_matrix.0;
+        let labels_match = columns.len() == n_cols
+            && columns.iter().enumerate().all(|(col, labels)| {
+                labels.len() == n_labels.saturating_sub(col * n).min(n)
+            });
+        if !labels_match {
+            return Err(MerkleError::MalformedTree.into());
+        }
+        Ok(())
+    }
+
+    /// Returns the initialized Prover, or gives back the Initializer if it
+    /// has not finished (or was cancelled).
+    pub fn finish(self) -> std::result::Result<Prover, Box<Initializer>> {
+        if !self.is_done() {
+            return Err(Box::new(self));
+        }
+        Ok(Prover::from_parts(
+            self.params,
+            self.nonce,
+            self.prover_id,
+            self.edges,
+            self.label_matrix,
+            self.merkle_tree.finish(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkle::Arity;
+    use crate::params::Space;
+
+    fn restored(initializer: &Initializer) -> Initializer {
+        let bytes = bincode::serialize(initializer).unwrap();
+        bincode::deserialize(&bytes).unwrap()
+    }
+
+    #[test]
+    fn resumed_initialization_matches_prover_new() {
+        let params = ProtoParams::new(Space::Kbs(8)).unwrap();
+        let (nonce, prover_id) = (vec![1, 2, 3], vec![4, 5]);
+        let mut initializer = Initializer::with_cached_layers(
+            params.clone(),
+            nonce.clone(),
+            prover_id.clone(),
+            2,
+        )
+        .unwrap();
+        let edges = initializer.edges.clone();
+
+        // Serialize and resume after every step, as a page reload would.
+        let mut progress = initializer.progress();
+        assert_eq!(progress, 0.0);
+        while !initializer.is_done() {
+            let next_progress = initializer.step(37);
+            assert!(next_progress > progress);
+            progress = next_progress;
+            initializer = restored(&initializer);
+            initializer.validate().unwrap();
+        }
+        assert_eq!(initializer.progress(), 1.0);
+        assert_eq!(initializer.step(37), 1.0);
+
+        let resumed = initializer.finish().unwrap();
+        let prover =
+            Prover::from_edges(params, nonce, prover_id, edges, usize::MAX)
+                .unwrap();
+        assert_eq!(resumed.merkle_root(), prover.merkle_root());
+        assert_eq!(
+            resumed.commitment().digest().unwrap(),
+            prover.commitment().digest().unwrap()
+        );
+    }
+
+    #[test]
+    fn corrupted_state_is_rejected() {
+        let params = ProtoParams::new(Space::Kbs(4)).unwrap();
+        let (n, k, arity) = (params.n, params.k, params.arity);
+        let mut initializer =
+            Initializer::with_cached_layers(params, vec![1], vec![2], 2)
+                .unwrap();
+        initializer.step(37);
+        assert!(restored(&initializer).validate().is_ok());
+
+        let mut missing_labels = restored(&initializer);
+        missing_labels.label_matrix = LabelMatrix::empty(k);
+        assert_eq!(
+            missing_labels.validate().unwrap_err().to_string(),
+            "merkle error: MalformedTree"
+        );
+
+        let mut wrong_arity = restored(&initializer);
+        wrong_arity.params.arity = Arity::Octal;
+        assert!(wrong_arity.validate().is_err());
+
+        let mut restarted_tree = restored(&initializer);
+        restarted_tree.merkle_tree = MerkleTreeBuilder::new(n * k, arity, 2);
+        assert!(restarted_tree.validate().is_err());
+
+        let mut wrong_size = restored(&initializer);
+        wrong_size.params.n *= 2;
+        assert!(wrong_size.validate().is_err());
+
+        initializer.cancel();
+        assert!(restored(&initializer).validate().is_ok());
+    }
+}
@@ -1,10 +1,24 @@
-mod graph;
-mod hasher;
-mod merkle;
-mod params;
-mod prover;
+mod bindings;
+pub mod challenge;
+pub mod clock;
+mod error;
+pub mod expansion;
+pub mod graph;
+pub mod hasher;
+pub mod init;
+pub mod merkle;
+pub mod params;
+pub mod pebbling;
+pub mod prover;
+pub mod receipt;
+pub mod replica;
+pub mod session;
+pub mod sim;
+pub mod spacetime;
 mod utils;
-mod verifier;
+pub mod verifier;
+
+pub use error::{Error, Result};
 
 use wasm_bindgen::prelude::*;
 
@@ -27,19 +41,29 @@ extern {
 pub fn main() {
     set_panic_hook();
 
-    let params = ProtoParams::new(Space::Kbs(4));
+    if let Err(e) = demo() {
+        log(&format!("error => {}", e));
+    }
+}
+
+fn demo() -> Result<()> {
+    let params = ProtoParams::new(Space::Kbs(4))?;
     let nonce = vec![];
+    let prover_id = vec![];
 
     log(&format!("{:#?}", params));
 
-    let mut verifier = Verifier::new(params.clone(), nonce);
-    let mut prover = Prover::new(params, verifier.nonce().to_vec());
+    let mut verifier =
+        Verifier::new(params.clone(), nonce, prover_id.clone())?;
+    let mut prover =
+        Prover::new(params, verifier.nonce().to_vec(), prover_id)?;
     let graph_edges = prover.edges().clone();
     let graph_commit = prover.merkle_root().to_vec();
-    verifier.set_graph_description(graph_edges, graph_commit);
+    verifier.set_graph_description(graph_edges, graph_commit)?;
     let challenge_vertices = verifier.gen_challenge();
-    let proofs = prover.create_proofs(&challenge_vertices);
+    let proofs = prover.create_proofs(&challenge_vertices)?;
     let verification_res = verifier.verify_proofs(&proofs);
 
     log(&format!("res => {:?}", verification_res));
+    Ok(())
 }
@@ -0,0 +1,399 @@
+//! A command-line driver for running and debugging Proof-of-Space sessions.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use pots::challenge::ChallengeDistribution;
+use pots::merkle::{Arity, MerkleProof};
+use pots::params::{ProtoParams, Space};
+use pots::prover::{Commitment, Prover};
+use pots::verifier::{SavedChallenge, Verifier};
+
+const USAGE: &str = "\
+Usage:
+    pots params <space>
+    pots nonce
+    pots key <key-file>
+    pots init <space> <nonce> <prover-dir> [--prover-id <hex>]
+              [--cached-layers <n>] [<param-options>]
+    pots challenge <space> <nonce> <key-file> <commitment-file>
+                   <challenge-file> [--prover-id <hex>] [<param-options>]
+    pots prove <prover-dir> <challenge-file> <proof-file>
+    pots verify <space> <nonce> <key-file> <commitment-file>
+                <challenge-file> <proof-file> [--prover-id <hex>]
+                [<param-options>]
+
+<param-options>:
+    [--arity <2|4|8>]
+    [--distribution <uniform|last-column|stratified|deep>]
+    [--min-spectral-gap <gap>]
+
+<space> is a number of bytes with an optional KB, MB or GB suffix (e.g. 4KB).
+`nonce` prints a fresh random nonce for the Verifier to send to the Prover.
+`key` writes a fresh random key to <key-file>, which the Verifier must keep
+secret: `challenge` tags the challenge file with it, and `verify` only
+accepts a challenge file that it tags.
+`init` writes the Prover's state to <prover-dir>/prover.bin and its
+commitment to <prover-dir>/commitment.bin. `challenge` and `verify` are run
+by the Verifier with its own space, nonce and param options, and only
+accept a commitment made for them by the Prover given by --prover-id (by
+default, the empty identity).";
+
+const PROVER_FILE: &str = "prover.bin";
+const COMMITMENT_FILE: &str = "commitment.bin";
+const NONCE_LENGTH: usize = 32;
+const KEY_LENGTH: usize = 32;
+
+type CliResult<T> = Result<T, String>;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Err(e) = run(&args) {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run(args: &[String]) -> CliResult<()> {
+    let (cmd, args) = args.split_first().ok_or(USAGE)?;
+    match (cmd.as_str(), args) {
+        ("params", [space]) => cmd_params(space),
+        ("nonce", []) => cmd_nonce(),
+        ("key", [key]) => cmd_key(key),
+        ("init", [space, nonce, dir, opts @ ..]) => {
+            cmd_init(space, nonce, dir, opts)
+        }
+        (
+            "challenge",
+            [space, nonce, key, commitment, challenge, opts @ ..],
+        ) => cmd_challenge(space, nonce, key, commitment, challenge, opts),
+        ("prove", [dir, challenge, proof]) => cmd_prove(dir, challenge, proof),
+        (
+            "verify",
+            [space, nonce, key, commitment, challenge, proof, opts @ ..],
+        ) => cmd_verify(space, nonce, key, commitment, challenge, proof, opts),
+        ("help", _) | ("--help", _) | ("-h", _) => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn cmd_params(space: &str) -> CliResult<()> {
+    let params =
+        ProtoParams::new(parse_space(space)?).map_err(|e| e.to_string())?;
+    println!("{:#?}", params);
+    Ok(())
+}
+
+fn cmd_nonce() -> CliResult<()> {
+    let mut nonce = vec![0; NONCE_LENGTH];
+    OsRng::new()
+        .map_err(|e| e.to_string())?
+        .fill_bytes(&mut nonce);
+    println!("{}", to_hex(&nonce));
+    Ok(())
+}
+
+fn cmd_key(key: &str) -> CliResult<()> {
+    let mut key_bytes = vec![0; KEY_LENGTH];
+    OsRng::new()
+        .map_err(|e| e.to_string())?
+        .fill_bytes(&mut key_bytes);
+    fs::write(key, key_bytes)
+        .map_err(|e| format!("could not write {}: {}", key, e))
+}
+
+fn cmd_init(
+    space: &str,
+    nonce: &str,
+    dir: &str,
+    opts: &[String],
+) -> CliResult<()> {
+    let mut params =
+        ProtoParams::new(parse_space(space)?).map_err(|e| e.to_string())?;
+    let nonce = parse_hex(nonce)?;
+    let mut prover_id = vec![];
+    let mut n_cached_layers = usize::MAX;
+
+    for opt in opts.chunks(2) {
+        match opt {
+            [flag, value] if flag == "--prover-id" => {
+                prover_id = parse_hex(value)?;
+            }
+            [flag, value] if flag == "--cached-layers" => {
+                n_cached_layers = value
+                    .parse()
+                    .map_err(|_| format!("invalid layer count: {}", value))?;
+            }
+            [flag, value] => params = parse_param_opt(params, flag, value)?,
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+
+    let prover =
+        Prover::with_cached_layers(params, nonce, prover_id, n_cached_layers)
+            .map_err(|e| e.to_string())?;
+    let dir = Path::new(dir);
+    fs::create_dir_all(dir)
+        .map_err(|e| format!("could not create {}: {}", dir.display(), e))?;
+    write_file(&dir.join(PROVER_FILE), &prover)?;
+    write_file(&dir.join(COMMITMENT_FILE), &prover.commitment())?;
+    println!("merkle root: {}", to_hex(prover.merkle_root()));
+    Ok(())
+}
+
+fn cmd_challenge(
+    space: &str,
+    nonce: &str,
+    key: &str,
+    commitment: &str,
+    challenge: &str,
+    opts: &[String],
+) -> CliResult<()> {
+    let key = read_key(key)?;
+    let mut verifier = verifier_for(space, nonce, commitment, opts)?;
+    let challenge_indices = verifier.gen_challenge();
+    let saved = verifier.save_challenge(&key).map_err(|e| e.to_string())?;
+    write_file(Path::new(challenge), &saved)?;
+    println!("challenged {} vertices", challenge_indices.len());
+    Ok(())
+}
+
+fn cmd_prove(dir: &str, challenge: &str, proof: &str) -> CliResult<()> {
+    let mut prover: Prover = read_file(&Path::new(dir).join(PROVER_FILE))?;
+    prover.validate().map_err(|e| e.to_string())?;
+    let saved: SavedChallenge = read_file(Path::new(challenge))?;
+    let proofs = prover
+        .create_proofs(&saved.indices)
+        .map_err(|e| e.to_string())?;
+    write_file(Path::new(proof), &proofs)?;
+    println!("answered {} challenges", proofs.len());
+    Ok(())
+}
+
+fn cmd_verify(
+    space: &str,
+    nonce: &str,
+    key: &str,
+    commitment: &str,
+    challenge: &str,
+    proof: &str,
+    opts: &[String],
+) -> CliResult<()> {
+    let key = read_key(key)?;
+    let mut verifier = verifier_for(space, nonce, commitment, opts)?;
+    let saved: SavedChallenge = read_file(Path::new(challenge))?;
+    let proofs: Vec<MerkleProof> = read_file(Path::new(proof))?;
+
+    verifier
+        .restore_challenge(&saved, &key)
+        .map_err(|e| e.to_string())?;
+    verifier
+        .verify_proofs(&proofs)
+        .map_err(|e| format!("verification failed: {:?}", e))?;
+    println!("verification succeeded");
+    Ok(())
+}
+
+/// Creates the Verifier described by the command line and gives it the
+/// Prover's commitment, which must have been made for the Verifier's params,
+/// nonce and prover identity rather than ones the Prover chose.
+fn verifier_for(
+    space: &str,
+    nonce: &str,
+    commitment: &str,
+    opts: &[String],
+) -> CliResult<Verifier> {
+    let mut params =
+        ProtoParams::new(parse_space(space)?).map_err(|e| e.to_string())?;
+    let nonce = parse_hex(nonce)?;
+    let mut prover_id = vec![];
+    for opt in opts.chunks(2) {
+        match opt {
+            [flag, value] if flag == "--prover-id" => {
+                prover_id = parse_hex(value)?;
+            }
+            [flag, value] => params = parse_param_opt(params, flag, value)?,
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+
+    let commitment: Commitment = read_file(Path::new(commitment))?;
+    if commitment.params != params
+        || commitment.nonce != nonce
+        || commitment.prover_id != prover_id
+    {
+        return Err("commitment was made for different params, nonce or \
+                    prover identity"
+            .to_string());
+    }
+    let mut verifier =
+        Verifier::new(params, nonce, prover_id).map_err(|e| e.to_string())?;
+    verifier
+        .set_graph_description(commitment.edges, commitment.merkle_root)
+        .map_err(|e| e.to_string())?;
+    Ok(verifier)
+}
+
+/// Applies one of the options shared by every command that derives the
+/// protocol parameters.
+fn parse_param_opt(
+    params: ProtoParams,
+    flag: &str,
+    value: &str,
+) -> CliResult<ProtoParams> {
+    match flag {
+        "--arity" => Ok(params.with_arity(parse_arity(value)?)),
+        "--distribution" => {
+            Ok(params.with_distribution(parse_distribution(value)?))
+        }
+        "--min-spectral-gap" => {
+            let gap = value
+                .parse()
+                .map_err(|_| format!("invalid spectral gap: {}", value))?;
+            Ok(params.with_min_spectral_gap(gap))
+        }
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn parse_space(s: &str) -> CliResult<Space> {
+    let s = s.to_uppercase();
+    let (digits, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(unit_start) => s.split_at(unit_start),
+        None => (s.as_str(), ""),
+    };
+    let size: usize =
+        digits.parse().map_err(|_| format!("invalid space: {}", s))?;
+    match unit {
+        "" | "B" => Ok(Space::Bytes(size)),
+        "KB" => Ok(Space::Kbs(size)),
+        "MB" => Ok(Space::Mbs(size)),
+        "GB" => Ok(Space::Gbs(size)),
+        _ => Err(format!("invalid space unit: {}", unit)),
+    }
+}
+
+fn parse_arity(s: &str) -> CliResult<Arity> {
+    match s {
+        "2" => Ok(Arity::Binary),
+        "4" => Ok(Arity::Quaternary),
+        "8" => Ok(Arity::Octal),
+        _ => Err(format!("unsupported arity: {}", s)),
+    }
+}
+
+fn parse_distribution(s: &str) -> CliResult<ChallengeDistribution> {
+    match s {
+        "uniform" => Ok(ChallengeDistribution::Uniform),
+        "last-column" => Ok(ChallengeDistribution::LastColumn),
+        "stratified" => Ok(ChallengeDistribution::Stratified),
+        "deep" => Ok(ChallengeDistribution::DeepWeighted),
+        _ => Err(format!("unsupported distribution: {}", s)),
+    }
+}
+
+fn parse_hex(s: &str) -> CliResult<Vec<u8>> {
+    if !s.is_ascii() || !s.len().is_multiple_of(2) {
+        return Err(format!("invalid hex: {}", s));
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&s[i..i + 2], 16)
+                .map_err(|_| format!("invalid hex: {}", s))
+        })
+        .collect()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn read_key(key: &str) -> CliResult<Vec<u8>> {
+    let key_bytes =
+        fs::read(key).map_err(|e| format!("could not read {}: {}", key, e))?;
+    if key_bytes.len() != KEY_LENGTH {
+        return Err(format!("{} is not a key written by `pots key`", key));
+    }
+    Ok(key_bytes)
+}
+
+fn read_file<T: DeserializeOwned>(path: &Path) -> CliResult<T> {
+    let bytes = fs::read(path)
+        .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+    bincode::deserialize(&bytes)
+        .map_err(|e| format!("could not decode {}: {}", path.display(), e))
+}
+
+fn write_file<T: Serialize>(path: &Path, value: &T) -> CliResult<()> {
+    let bytes = bincode::serialize(value)
+        .map_err(|e| format!("could not encode {}: {}", path.display(), e))?;
+    fs::write(path, bytes)
+        .map_err(|e| format!("could not write {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_args(args: &[&str]) -> CliResult<()> {
+        let args: Vec<String> =
+            args.iter().map(|arg| arg.to_string()).collect();
+        run(&args)
+    }
+
+    #[test]
+    fn sessions_round_trip_through_files() {
+        let dir = env::temp_dir().join(format!("pots-cli-{}", process::id()));
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        let (prover_dir, key, other_key) =
+            (path("prover"), path("key"), path("other-key"));
+        let commitment = dir.join("prover").join(COMMITMENT_FILE);
+        let commitment = commitment.to_str().unwrap();
+        let (challenge, proof) = (path("challenge"), path("proof"));
+        let nonce = "0102";
+        let verify = |key: &str| {
+            run_args(&[
+                "verify", "8KB", nonce, key, commitment, &challenge, &proof,
+                "--prover-id", "0405",
+            ])
+        };
+
+        run_args(&["params", "8KB"]).unwrap();
+        run_args(&["init", "8KB", nonce, &prover_dir, "--prover-id", "0405"])
+            .unwrap();
+        run_args(&["key", &key]).unwrap();
+        run_args(&[
+            "challenge", "8KB", nonce, &key, commitment, &challenge,
+            "--prover-id", "0405",
+        ])
+        .unwrap();
+        run_args(&["prove", &prover_dir, &challenge, &proof]).unwrap();
+        assert_eq!(verify(&key), Ok(()));
+
+        // Only the key that tagged the challenge can restore it.
+        run_args(&["key", &other_key]).unwrap();
+        assert!(verify(&other_key).unwrap_err().contains("InvalidTag"));
+
+        // A challenge that the Prover picked is rejected, even if its proofs
+        // are valid.
+        let mut saved: SavedChallenge =
+            read_file(Path::new(&challenge)).unwrap();
+        saved.indices.reverse();
+        write_file(Path::new(&challenge), &saved).unwrap();
+        run_args(&["prove", &prover_dir, &challenge, &proof]).unwrap();
+        assert!(verify(&key).unwrap_err().contains("InvalidTag"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
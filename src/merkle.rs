@@ -1,108 +1,706 @@
-use crate::graph::{LabelMatrix, VertexLabel};
+use serde::{Deserialize, Serialize};
+
+use crate::graph::LabelMatrix;
 use crate::hasher::Hasher;
 
 pub type MerkleLabel = Vec<u8>;
 
+/// A Merkle path is laid out as: the opened leaf, followed by `arity - 1`
+/// sibling labels for each non-root layer (ordered by their position within
+/// the sibling group), followed by the root.
 pub type MerklePath = Vec<MerkleLabel>;
 
+/// The number of inputs hashed together to produce each Merkle node.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub enum Arity {
+    #[default]
+    Binary,
+    Quaternary,
+    Octal,
+}
+
+impl Arity {
+    pub fn n_children(&self) -> usize {
+        match self {
+            Arity::Binary => 2,
+            Arity::Quaternary => 4,
+            Arity::Octal => 8,
+        }
+    }
+}
+
 /// Given a vertex's index within a Merkle Tree layer, this function returns
-/// `true` if the vertex is the left input for a child node.
-pub fn is_left(index_within_layer: usize) -> bool {
-    index_within_layer % 2 == 0
+/// the vertex's position within the group of inputs hashed into its child
+/// node.
+pub fn position_in_group(index_within_layer: usize, arity: Arity) -> usize {
+    index_within_layer % arity.n_children()
+}
+
+/// Given a vertex's index within a Merkle Tree layer, this function returns
+/// the index of the child node it is hashed into within the next layer.
+pub fn child_index(index_within_layer: usize, arity: Arity) -> usize {
+    index_within_layer / arity.n_children()
+}
+
+/// Returns the number of layers (including the leaf layer and the root) in a
+/// Merkle tree built over `n_leaves` leaves once the leaves have been padded
+/// to the next power of the tree's arity.
+pub fn n_layers(n_leaves: usize, arity: Arity) -> usize {
+    let mut n_layers = 1;
+    let mut n_padded_leaves: usize = 1;
+    while n_padded_leaves < n_leaves {
+        n_layers += 1;
+        match n_padded_leaves.checked_mul(arity.n_children()) {
+            Some(n) => n_padded_leaves = n,
+            None => break,
+        }
+    }
+    n_layers
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MerkleError {
+    /// The root calculated from a path of `depth` non-root layers is not the
+    /// root at the end of the path.
+    CalculatedRootDoesNotMatchProof {
+        depth: usize,
+        calculated: MerkleLabel,
+    },
+    /// The root calculated from a path of `depth` non-root layers is not the
+    /// expected root.
+    CalculatedRootDoesNotMatchRoot {
+        depth: usize,
+        calculated: MerkleLabel,
+    },
+    IndexDoesNotMatchProof,
+    /// The opened leaf is not one of the tree's `n_leaves` leaves.
+    IndexOutOfRange { index: usize, n_leaves: usize },
+    LeafDoesNotMatchProof,
+    MalformedPath,
+    /// The path's depth is not that of a tree with the expected number of
+    /// leaves, e.g. a shorter path that opens an internal node as if it were
+    /// a leaf.
+    UnexpectedDepth { expected: usize, depth: usize },
+    /// The tree's stored layers (or the labels it was opened with) are
+    /// inconsistent with its dimensions, e.g. after a corrupted
+    /// deserialization.
+    MalformedTree,
 }
 
 /// The Prover creates a `MerkleProof` for each vertex in the Verifier's
 /// challenge set.
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct MerkleProof {
     pub challenge_index: usize,
     pub path: MerklePath,
+    pub arity: Arity,
 }
 
-#[derive(Debug)]
-pub struct MerkleTree(Vec<Vec<MerkleLabel>>);
-
-impl MerkleTree {
-    pub fn from_label_matrix(label_matrix: &LabelMatrix) -> Self {
-        let mut leaves: Vec<VertexLabel> = label_matrix
-            .0
-            .iter()
-            .flat_map(|col_labels| col_labels.iter().cloned())
-            .collect();
-
-        let n_layers = {
-            let n_leaves_init = leaves.len();
-            let n_layers_init = (n_leaves_init as f32).log2();
-            // If the number of leaves is not a power of two, add dataless
-            // leaves until the number of leaves is a power of two.
-            if n_layers_init.fract() != 0.0 {
-                let n_layers_final = n_layers_init.trunc() + 1.0;
-                let n_leaves_final = 2.0f32.powf(n_layers_final) as usize;
-                leaves.resize(n_leaves_final, vec![]);
-                n_layers_final as usize + 1
-            } else {
-                n_layers_init as usize + 1
-            }
-        };
+impl MerkleProof {
+    /// Checks that this proof opens `leaf` at index `leaf_index` of the Merkle
+    /// tree of `n_leaves` leaves whose root is `root`. The path must be
+    /// exactly as deep as that tree.
+    pub fn verify(
+        &self,
+        root: &MerkleLabel,
+        n_leaves: usize,
+        leaf_index: usize,
+        leaf: &MerkleLabel,
+    ) -> Result<(), MerkleError> {
+        let mut hasher = Hasher::new();
+        self.verify_with_hasher(&mut hasher, root, n_leaves, leaf_index, leaf)
+    }
 
+    /// Checks each `(leaf_index, leaf, proof)` opening against `root` and a
+    /// tree of `n_leaves` leaves, stopping at the first invalid opening.
+    pub fn verify_batch<'a, I>(
+        root: &MerkleLabel,
+        n_leaves: usize,
+        openings: I,
+    ) -> Result<(), MerkleError>
+    where
+        I: IntoIterator<Item = (usize, &'a MerkleLabel, &'a MerkleProof)>,
+    {
         let mut hasher = Hasher::new();
-        let mut tree = vec![leaves];
-
-        for layer_index in 1..n_layers {
-            let mut curr_layer = vec![];
-            let prev_layer = &tree[layer_index - 1];
-            for two_labels in prev_layer.chunks(2) {
-                let left_input = &two_labels[0];
-                let right_input = &two_labels[1];
-                let merkle_label = hasher.label_merkle_node(
-                    left_input,
-                    right_input,
-                );
-                curr_layer.push(merkle_label);
-            }
-            tree.push(curr_layer);
+        for (leaf_index, leaf, proof) in openings {
+            proof.verify_with_hasher(
+                &mut hasher,
+                root,
+                n_leaves,
+                leaf_index,
+                leaf,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// The number of non-root layers in the tree that this proof was opened
+    /// from, or `None` if the path's length is not valid for its arity.
+    pub fn depth(&self) -> Option<usize> {
+        let n_siblings = self.arity.n_children() - 1;
+        let n_layer_labels = self.path.len().checked_sub(2)?;
+        if n_layer_labels % n_siblings == 0 {
+            Some(n_layer_labels / n_siblings)
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn verify_with_hasher(
+        &self,
+        hasher: &mut Hasher,
+        root: &MerkleLabel,
+        n_leaves: usize,
+        leaf_index: usize,
+        leaf: &MerkleLabel,
+    ) -> Result<(), MerkleError> {
+        if leaf_index >= n_leaves {
+            return Err(MerkleError::IndexOutOfRange {
+                index: leaf_index,
+                n_leaves,
+            });
+        }
+        let expected = n_layers(n_leaves, self.arity) - 1;
+        let depth = self.depth().ok_or(MerkleError::MalformedPath)?;
+        if depth != expected {
+            return Err(MerkleError::UnexpectedDepth { expected, depth });
+        }
+        if leaf_index != self.challenge_index {
+            return Err(MerkleError::IndexDoesNotMatchProof);
+        }
+        if leaf != &self.path[0] {
+            return Err(MerkleError::LeafDoesNotMatchProof);
         }
 
-        MerkleTree(tree)
+        // Reconstruct the Merkle Tree then calculate its root. Each chunk of
+        // siblings combined with the current label is hashed into the label
+        // of the child node in the next layer.
+        let n_siblings = self.arity.n_children() - 1;
+        let mut child_label = leaf.clone();
+        let mut child_index = leaf_index;
+        for siblings in self.path[1..(self.path.len() - 1)].chunks(n_siblings)
+        {
+            let position = position_in_group(child_index, self.arity);
+            let mut inputs: Vec<&MerkleLabel> = siblings.iter().collect();
+            inputs.insert(position, &child_label);
+            child_label = hasher.label_merkle_node(&inputs);
+            child_index = self::child_index(child_index, self.arity);
+        }
+        let calculated_root = child_label;
+
+        if &calculated_root != root {
+            Err(MerkleError::CalculatedRootDoesNotMatchRoot {
+                depth,
+                calculated: calculated_root,
+            })
+        } else if self.path.last() != Some(&calculated_root) {
+            Err(MerkleError::CalculatedRootDoesNotMatchProof {
+                depth,
+                calculated: calculated_root,
+            })
+        } else {
+            Ok(())
+        }
     }
+}
 
+/// A Merkle tree over the labels of a `LabelMatrix`. Only the topmost layers
+/// of the tree are stored; the layers below them are recomputed from the
+/// labels whenever a leaf is opened.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MerkleTree {
+    arity: Arity,
+    n_layers: usize,
+    /// The stored layers, ordered from the lowest stored layer to the root.
+    layers: Vec<Vec<MerkleLabel>>,
+}
+
+impl MerkleTree {
+    /// Builds a Merkle tree that stores only its top `n_cached_layers`
+    /// layers (at least the root is always stored).
+    pub fn from_label_matrix(
+        label_matrix: &LabelMatrix,
+        arity: Arity,
+        n_cached_layers: usize,
+    ) -> Self {
+        let mut builder = MerkleTreeBuilder::new(
+            label_matrix.n_labels(),
+            arity,
+            n_cached_layers,
+        );
+        for col_labels in &label_matrix.0 {
+            for label in col_labels {
+                builder.push_leaf(label.clone());
+            }
+        }
+        builder.finish()
+    }
+
+    /// The tree's root. A tree without any stored layers (which can only
+    /// come from corrupted state) has an empty root, which no proof matches.
     pub fn root(&self) -> &MerkleLabel {
-        &self.0.last().unwrap()[0]
+        self.layers
+            .last()
+            .and_then(|root_layer| root_layer.first())
+            .unwrap_or(&PADDING_LEAF)
+    }
+
+    /// The number of leaves in the tree, including padding.
+    pub fn n_leaves(&self) -> usize {
+        let n_layers = self.n_layers.saturating_sub(1);
+        self.arity.n_children().saturating_pow(n_layers as u32)
+    }
+
+    pub fn arity(&self) -> Arity {
+        self.arity
     }
 
     fn n_layers(&self) -> usize {
-        self.0.len()
+        self.n_layers
+    }
+
+    /// Checks that the tree's stored layers are those of a finished tree over
+    /// `n_leaves` leaves with the given arity, so that a tree restored from
+    /// untrusted bytes cannot panic or overflow when it is opened.
+    pub(crate) fn check(
+        &self,
+        n_leaves: usize,
+        arity: Arity,
+    ) -> Result<(), MerkleError> {
+        let n_children = arity.n_children();
+        let n_layers = n_layers(n_leaves, arity);
+        let n_padded_leaves = n_children.checked_pow(n_layers as u32 - 1);
+        let dimensions_match = self.arity == arity
+            && self.n_layers == n_layers
+            && n_padded_leaves.is_some()
+            && !self.layers.is_empty()
+            && self.layers.len() <= n_layers;
+        if !dimensions_match {
+            return Err(MerkleError::MalformedTree);
+        }
+
+        // The layer at `layer_index` holds `n_children^(n_layers - 1 -
+        // layer_index)` nodes.
+        let lowest_cached_layer = self.lowest_cached_layer();
+        let layers_match =
+            self.layers.iter().enumerate().all(|(i, layer)| {
+                let height = n_layers - 1 - (lowest_cached_layer + i);
+                layer.len() == n_children.pow(height as u32)
+            });
+        if !layers_match {
+            return Err(MerkleError::MalformedTree);
+        }
+        Ok(())
     }
 
-    #[allow(dead_code)]
-    fn n_leaves(&self) -> usize {
-        self.0[0].len()
+    /// The index of the lowest layer kept in memory.
+    fn lowest_cached_layer(&self) -> usize {
+        self.n_layers.saturating_sub(self.layers.len())
     }
 
-    pub fn open(&self, vertex_index: usize) -> MerklePath {
-        let mut path = vec![];
-        let mut curr_index = vertex_index;
-        let curr_merkle_label = self.0[0][curr_index].clone();
-        path.push(curr_merkle_label);
+    /// Opens the leaf at `vertex_index`, recomputing any uncached layers from
+    /// the labels in `label_matrix`.
+    pub fn open(
+        &self,
+        label_matrix: &LabelMatrix,
+        vertex_index: usize,
+    ) -> Result<MerklePath, MerkleError> {
+        let n_leaves = self.n_leaves();
+        if vertex_index >= n_leaves {
+            return Err(MerkleError::IndexOutOfRange {
+                index: vertex_index,
+                n_leaves,
+            });
+        }
+        if self.layers.is_empty() || self.layers.len() > self.n_layers {
+            return Err(MerkleError::MalformedTree);
+        }
+        let n_children = self.arity.n_children();
+        let lowest_cached_layer = self.lowest_cached_layer();
 
-        for layer_index in 0..(self.n_layers() - 1) {
-            let sibling_index = if curr_index % 2 == 0 {
-                curr_index + 1
+        // Recompute the uncached layers of the subtree containing
+        // `vertex_index`; the subtree's root is the lowest cached ancestor of
+        // the opened leaf.
+        let subtree_n_leaves = n_children
+            .checked_pow(lowest_cached_layer as u32)
+            .ok_or(MerkleError::MalformedTree)?;
+        let subtree_first_leaf =
+            vertex_index - vertex_index % subtree_n_leaves;
+        let subtree = if lowest_cached_layer > 0 {
+            let mut builder = MerkleTreeBuilder::new(
+                subtree_n_leaves,
+                self.arity,
+                usize::MAX,
+            );
+            for leaf_index in
+                subtree_first_leaf..(subtree_first_leaf + subtree_n_leaves)
+            {
+                let leaf = get_leaf(label_matrix, leaf_index)
+                    .ok_or(MerkleError::MalformedTree)?;
+                builder.push_leaf(leaf.clone());
+            }
+            builder.finish().layers
+        } else {
+            vec![]
+        };
+
+        let get_label = |layer_index: usize, index: usize| {
+            let label = if layer_index < lowest_cached_layer {
+                let subtree_first_index =
+                    subtree_first_leaf / n_children.pow(layer_index as u32);
+                subtree
+                    .get(layer_index)
+                    .and_then(|layer| layer.get(index - subtree_first_index))
             } else {
-                curr_index - 1
+                self.layers
+                    .get(layer_index - lowest_cached_layer)
+                    .and_then(|layer| layer.get(index))
             };
+            label.cloned().ok_or(MerkleError::MalformedTree)
+        };
+
+        let mut path = vec![];
+        let mut curr_index = vertex_index;
+        path.push(get_label(0, curr_index)?);
 
-            let sibling_merkle_label =
-                self.0[layer_index][sibling_index].clone();
+        for layer_index in 0..(self.n_layers() - 1) {
+            let first_sibling_index =
+                curr_index - position_in_group(curr_index, self.arity);
 
-            path.push(sibling_merkle_label);
+            for sibling_index in
+                first_sibling_index..(first_sibling_index + n_children)
+            {
+                if sibling_index != curr_index {
+                    path.push(get_label(layer_index, sibling_index)?);
+                }
+            }
 
             // Get the index of the child node in the next layer.
-            let child_index = (curr_index as f32 / 2.0).floor() as usize;
-            curr_index = child_index;
+            curr_index = child_index(curr_index, self.arity);
         }
 
         path.push(self.root().to_vec());
-        path
+        Ok(path)
+    }
+}
+
+/// The label of a dataless leaf used to pad the Merkle tree.
+static PADDING_LEAF: MerkleLabel = Vec::new();
+
+/// Returns the Merkle leaf at `leaf_index`, leaves are taken from the label
+/// matrix in column order. Returns `None` if the label matrix is missing a
+/// label within one of its columns.
+fn get_leaf(
+    label_matrix: &LabelMatrix,
+    leaf_index: usize,
+) -> Option<&MerkleLabel> {
+    let n = label_matrix.0.first().map_or(0, |col_labels| col_labels.len());
+    if n == 0 {
+        return Some(&PADDING_LEAF);
+    }
+    match label_matrix.0.get(leaf_index / n) {
+        Some(col_labels) => col_labels.get(leaf_index % n),
+        None => Some(&PADDING_LEAF),
+    }
+}
+
+/// Builds a `MerkleTree` one leaf at a time, hashing each group of nodes as
+/// soon as it is complete so that only the cached layers and at most
+/// `arity - 1` nodes per uncached layer are held in memory.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MerkleTreeBuilder {
+    arity: Arity,
+    n_layers: usize,
+    n_padded_leaves: usize,
+    n_pushed_leaves: usize,
+    lowest_cached_layer: usize,
+    /// The nodes of each layer whose child has not been computed yet.
+    pending: Vec<Vec<MerkleLabel>>,
+    /// The cached layers, ordered from the lowest cached layer to the root.
+    layers: Vec<Vec<MerkleLabel>>,
+}
+
+impl MerkleTreeBuilder {
+    /// Creates a builder for a tree over `n_leaves` leaves that stores only
+    /// its top `n_cached_layers` layers.
+    pub fn new(n_leaves: usize, arity: Arity, n_cached_layers: usize) -> Self {
+        // If the number of leaves is not a power of the arity, dataless leaves
+        // are added until the number of leaves is a power of the arity.
+        let n_layers = n_layers(n_leaves, arity);
+        let n_padded_leaves = arity.n_children().pow(n_layers as u32 - 1);
+        let n_cached_layers = n_cached_layers.max(1).min(n_layers);
+
+        MerkleTreeBuilder {
+            arity,
+            n_layers,
+            n_padded_leaves,
+            n_pushed_leaves: 0,
+            lowest_cached_layer: n_layers - n_cached_layers,
+            pending: vec![vec![]; n_layers - 1],
+            layers: vec![vec![]; n_cached_layers],
+        }
+    }
+
+    /// The number of leaves in the finished tree, including padding.
+    pub fn n_padded_leaves(&self) -> usize {
+        self.n_padded_leaves
+    }
+
+    pub fn n_pushed_leaves(&self) -> usize {
+        self.n_pushed_leaves
+    }
+
+    /// Checks that the builder's state is that of a tree over `n_leaves`
+    /// leaves with the given arity after `n_pushed_leaves` pushes, so that a
+    /// builder restored from untrusted bytes cannot panic when resumed.
+    pub(crate) fn check(
+        &self,
+        n_leaves: usize,
+        arity: Arity,
+    ) -> Result<(), MerkleError> {
+        let n_children = arity.n_children();
+        let n_layers = n_layers(n_leaves, arity);
+        let n_padded_leaves = n_children.checked_pow(n_layers as u32 - 1);
+        let dimensions_match = self.arity == arity
+            && self.n_layers == n_layers
+            && Some(self.n_padded_leaves) == n_padded_leaves
+            && self.n_pushed_leaves <= self.n_padded_leaves
+            && self.lowest_cached_layer < n_layers
+            && self.pending.len() == n_layers - 1
+            && self.layers.len() == n_layers - self.lowest_cached_layer;
+        if !dimensions_match {
+            return Err(MerkleError::MalformedTree);
+        }
+
+        // Each layer holds one node per `n_children^layer_index` pushed
+        // leaves, of which the last `n_nodes % n_children` are pending.
+        let layers_match = (0..n_layers).all(|layer_index| {
+            let n_nodes =
+                self.n_pushed_leaves / n_children.pow(layer_index as u32);
+            let pending_matches = layer_index == n_layers - 1
+                || self.pending[layer_index].len() == n_nodes % n_children;
+            let cached_matches = layer_index < self.lowest_cached_layer
+                || self.layers[layer_index - self.lowest_cached_layer].len()
+                    == n_nodes;
+            pending_matches && cached_matches
+        });
+        if !layers_match {
+            return Err(MerkleError::MalformedTree);
+        }
+        Ok(())
+    }
+
+    /// Appends the next leaf to the tree.
+    pub fn push_leaf(&mut self, leaf: MerkleLabel) {
+        let n_children = self.arity.n_children();
+        let mut hasher = Hasher::new();
+        let mut node = leaf;
+        let mut layer_index = 0;
+
+        loop {
+            if layer_index >= self.lowest_cached_layer {
+                let cached_layer_index = layer_index - self.lowest_cached_layer;
+                self.layers[cached_layer_index].push(node.clone());
+            }
+            if layer_index == self.n_layers - 1 {
+                break;
+            }
+
+            let pending = &mut self.pending[layer_index];
+            pending.push(node);
+            if pending.len() < n_children {
+                break;
+            }
+            let inputs: Vec<&MerkleLabel> = pending.iter().collect();
+            node = hasher.label_merkle_node(&inputs);
+            pending.clear();
+            layer_index += 1;
+        }
+
+        self.n_pushed_leaves += 1;
+    }
+
+    /// Appends a dataless leaf to the tree.
+    pub fn push_padding_leaf(&mut self) {
+        self.push_leaf(vec![]);
+    }
+
+    /// Pads the tree with dataless leaves then returns the finished tree.
+    pub fn finish(mut self) -> MerkleTree {
+        while self.n_pushed_leaves < self.n_padded_leaves {
+            self.push_padding_leaf();
+        }
+        MerkleTree {
+            arity: self.arity,
+            n_layers: self.n_layers,
+            layers: self.layers,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ARITIES: [Arity; 3] =
+        [Arity::Binary, Arity::Quaternary, Arity::Octal];
+
+    /// `2^24 + 1` is the smallest leaf count that an `f32` cannot represent,
+    /// so `(n_leaves as f32).log2().ceil()` undercounts its layers.
+    const F32_INEXACT: usize = (1 << 24) + 1;
+
+    fn label_matrix(n: usize, k: usize) -> LabelMatrix {
+        let mut hasher = Hasher::new();
+        LabelMatrix(
+            (0..k)
+                .map(|col| {
+                    (0..n)
+                        .map(|vertex| {
+                            hasher.label_source(&[], col * n + vertex)
+                        })
+                        .collect()
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn n_layers_is_exact_past_f32_precision() {
+        assert_eq!((F32_INEXACT as f32).log2().ceil(), 24.0);
+        for (arity, n_layers_at_2_24) in ARITIES.iter().zip(&[25, 13, 9]) {
+            assert_eq!(n_layers(1 << 24, *arity), *n_layers_at_2_24);
+            assert_eq!(n_layers(F32_INEXACT, *arity), n_layers_at_2_24 + 1);
+        }
+        assert_eq!(n_layers(0, Arity::Binary), 1);
+        assert_eq!(n_layers(1, Arity::Binary), 1);
+        assert_eq!(n_layers(2, Arity::Binary), 2);
+        assert_eq!(n_layers(3, Arity::Binary), 3);
+        assert_eq!(n_layers(usize::MAX, Arity::Binary), 65);
+    }
+
+    #[test]
+    fn child_index_is_exact_past_f32_precision() {
+        // `2^24 + 3` rounds to `2^24 + 4` as an `f32`.
+        let index = (1 << 24) + 3;
+        assert_eq!(((index as f32) / 2.0) as usize, (1 << 23) + 2);
+        assert_eq!(child_index(index, Arity::Binary), (1 << 23) + 1);
+        assert_eq!(child_index(index, Arity::Quaternary), 1 << 22);
+        assert_eq!(child_index(index, Arity::Octal), 1 << 21);
+        assert_eq!(position_in_group(index, Arity::Binary), 1);
+        assert_eq!(position_in_group(index, Arity::Quaternary), 3);
+        assert_eq!(position_in_group(index, Arity::Octal), 3);
+
+        // The last leaf's ancestors lead to the root in `n_layers - 1` steps.
+        for arity in &ARITIES {
+            let n_layers = n_layers(F32_INEXACT, *arity);
+            let mut index = F32_INEXACT - 1;
+            for _ in 0..(n_layers - 2) {
+                index = child_index(index, *arity);
+                assert_ne!(index, 0);
+            }
+            assert_eq!(child_index(index, *arity), 0);
+        }
+    }
+
+    #[test]
+    fn opened_paths_verify() {
+        let label_matrix = label_matrix(5, 3);
+        for arity in &ARITIES {
+            for n_cached_layers in [1, 2, usize::MAX] {
+                let tree = MerkleTree::from_label_matrix(
+                    &label_matrix,
+                    *arity,
+                    n_cached_layers,
+                );
+                for index in 0..15 {
+                    let path = tree.open(&label_matrix, index).unwrap();
+                    let leaf = path[0].clone();
+                    let proof = MerkleProof {
+                        challenge_index: index,
+                        path,
+                        arity: *arity,
+                    };
+                    let root = tree.root();
+                    assert_eq!(proof.verify(root, 15, index, &leaf), Ok(()));
+                    assert_eq!(
+                        proof.verify(root, 15 * 16, index, &leaf),
+                        Err(MerkleError::UnexpectedDepth {
+                            expected: n_layers(15 * 16, *arity) - 1,
+                            depth: n_layers(15, *arity) - 1,
+                        })
+                    );
+                }
+                let index = tree.n_leaves();
+                assert_eq!(
+                    tree.open(&label_matrix, index),
+                    Err(MerkleError::IndexOutOfRange { index, n_leaves: index })
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn shorter_paths_are_rejected() {
+        // A path that skips the leaf layer opens an internal node as if it
+        // were a leaf of a smaller tree.
+        let label_matrix = label_matrix(5, 3);
+        for arity in &ARITIES {
+            let tree =
+                MerkleTree::from_label_matrix(&label_matrix, *arity, 1);
+            let path = tree.open(&label_matrix, 0).unwrap();
+            let n_siblings = arity.n_children() - 1;
+            let mut short_path = path[(1 + n_siblings)..].to_vec();
+            short_path.insert(0, path[1].clone());
+            let leaf = short_path[0].clone();
+            let proof = MerkleProof {
+                challenge_index: 0,
+                path: short_path,
+                arity: *arity,
+            };
+            assert_eq!(
+                proof.verify(tree.root(), 15, 0, &leaf),
+                Err(MerkleError::UnexpectedDepth {
+                    expected: n_layers(15, *arity) - 1,
+                    depth: n_layers(15, *arity) - 2,
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn corrupted_trees_are_rejected() {
+        let label_matrix = label_matrix(5, 3);
+        for n_cached_layers in [1, 2, usize::MAX] {
+            let tree = MerkleTree::from_label_matrix(
+                &label_matrix,
+                Arity::Binary,
+                n_cached_layers,
+            );
+            assert_eq!(tree.check(15, Arity::Binary), Ok(()));
+            assert!(tree.check(15, Arity::Quaternary).is_err());
+            assert!(tree.check(17, Arity::Binary).is_err());
+        }
+
+        // A tree claiming more layers than a `usize` can count leaves for.
+        let mut tree =
+            MerkleTree::from_label_matrix(&label_matrix, Arity::Octal, 1);
+        tree.n_layers = 100;
+        assert_eq!(tree.n_leaves(), usize::MAX);
+        assert_eq!(
+            tree.check(15, Arity::Octal),
+            Err(MerkleError::MalformedTree)
+        );
+        assert_eq!(
+            tree.open(&label_matrix, 0),
+            Err(MerkleError::MalformedTree)
+        );
+
+        let mut tree =
+            MerkleTree::from_label_matrix(&label_matrix, Arity::Binary, 2);
+        tree.layers[0].pop();
+        assert_eq!(
+            tree.check(15, Arity::Binary),
+            Err(MerkleError::MalformedTree)
+        );
     }
 }
@@ -1,6 +1,15 @@
 //! Derivation of protocol parameters from the Verifier's space requirement.
 
+use std::convert::TryFrom;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::challenge::{self, ChallengeDistribution};
+use crate::error::{Error, Result};
+use crate::graph::{GraphFamily, LabelingMode, IN_DEGREE};
 use crate::hasher::DIGEST_LENGTH;
+use crate::merkle::Arity;
 
 /// The minimum number of vertices per disjoint set in the graph. We use a
 /// constant in-degree of 16.
@@ -9,6 +18,16 @@ const MIN_N: usize = 16;
 /// The number of columns in the graph.
 const K: usize = 6;
 
+/// The number of columns in a DRSample overlay graph. Each column is itself
+/// depth-robust, so fewer columns are stacked than for the plain expander
+/// (which also shrinks the expander's challenge size, see
+/// `calc_family_l0`).
+const K_DRSAMPLE: usize = 4;
+
+/// The number of butterfly networks stacked back to back. Two back-to-back
+/// butterflies form a superconcentrator.
+const N_BUTTERFLIES: usize = 2;
+
 /// The minimum ammout of proveable space for the given security
 /// parameter `k` and label length.
 ///
@@ -20,8 +39,57 @@ const K: usize = 6;
 /// Using `k = 6`, the minimum space requirement is 4kb.
 const MIN_SPACE: usize = MIN_N * DIGEST_LENGTH * (K + 2);
 
+/// The fraction `e` of discarded labels that a challenge must detect, from
+/// the `n - n/delta > n/4` bound in `calc_min_delta`. A uniform challenge of
+/// `l0` labels misses every discarded label with probability at most
+/// `(1 - e)^l0`.
+pub(crate) const DETECTED_FRACTION: f64 = 0.25;
+
+/// The security parameter `lambda` of the spot-check bound used for butterfly
+/// graphs, which have no pebbling analysis (see `calc_spot_check_l0`).
+const SPOT_CHECK_BITS: usize = 20;
+
+#[derive(Debug, PartialEq)]
+pub enum ParamsError {
+    /// The space requirement is less than `min_space` bytes.
+    SpaceTooSmall { space: usize, min_space: usize },
+    /// Expander graphs require at least `min_n` (the in-degree) vertices
+    /// per column, and butterfly graphs at least two.
+    TooFewVertices { n: usize, min_n: usize },
+    /// The graph must have at least `min_k` columns.
+    TooFewColumns { k: usize, min_k: usize },
+    /// Butterfly graphs require `n` to be a power of two.
+    NotPowerOfTwo { n: usize },
+    /// The graph was sampled with `actual` vertices per column rather than
+    /// the parameters' `expected` number.
+    GraphSizeMismatch { expected: usize, actual: usize },
+    /// The graph was sampled from the `actual` family rather than the
+    /// parameters' `expected` one.
+    GraphFamilyMismatch {
+        expected: GraphFamily,
+        actual: GraphFamily,
+    },
+    /// None of `n_attempts` sampled graphs had a spectral gap of at least
+    /// `min_spectral_gap`.
+    InsufficientExpansion {
+        min_spectral_gap: f64,
+        n_attempts: usize,
+    },
+    /// The graph's spectral gap is below the parameters' minimum.
+    SpectralGapTooSmall {
+        spectral_gap: f64,
+        min_spectral_gap: f64,
+    },
+    /// The edges at `vertex` are not those of a graph in the edges' family
+    /// (e.g. a source or sink without exactly `IN_DEGREE` edges, or an edge to
+    /// a vertex outside the column).
+    MalformedEdges { vertex: usize },
+    /// A size derived from the parameters (the space in bytes, or the number
+    /// of labels) does not fit in a `usize`.
+    SizeOverflow,
+}
+
 /// The Verfier's space requirement.
-#[allow(dead_code)]
 #[derive(Debug)]
 pub enum Space {
     Bytes(usize),
@@ -31,18 +99,20 @@ pub enum Space {
 }
 
 impl Space {
-    pub fn n_bytes(&self) -> usize {
-        match self {
-            Space::Bytes(n_bytes) => *n_bytes,
-            Space::Kbs(n_kbs) => n_kbs * 1024,
-            Space::Mbs(n_mbs) => n_mbs * 1_048_576,
-            Space::Gbs(n_gbs) => n_gbs * 1_073_741_824,
-        }
+    /// The space in bytes. Fails if it does not fit in a `usize`.
+    pub fn n_bytes(&self) -> Result<usize> {
+        let n_bytes = match self {
+            Space::Bytes(n_bytes) => Some(*n_bytes),
+            Space::Kbs(n_kbs) => n_kbs.checked_mul(1024),
+            Space::Mbs(n_mbs) => n_mbs.checked_mul(1_048_576),
+            Space::Gbs(n_gbs) => n_gbs.checked_mul(1_073_741_824),
+        };
+        n_bytes.ok_or(Error::Params(ParamsError::SizeOverflow))
     }
 }
 
 /// Calculates the number of vertices per disoint set (i.e. the number of
-/// vertices per column in the graph).
+/// vertices per column in the graph) for a graph with `k` columns.
 ///
 /// Derivation:
 ///
@@ -52,13 +122,30 @@ impl Space {
 /// `N = n(kL + 2L)`
 /// `N / (Lk + 2L) = n`
 /// `N / L(k + 2)  = n`
-fn calc_n(space: usize) -> usize {
+fn calc_n(space: usize, k: usize) -> usize {
     let space = space as f32;
     let digest_length = DIGEST_LENGTH as f32;
-    let k = K as f32;
+    let k = k as f32;
     (space / (digest_length * (k + 2.0))).ceil() as usize
 }
 
+/// The number of columns in a stack of butterfly networks on `n` vertices:
+/// the sources plus `log2(n)` stages per butterfly.
+fn calc_k_butterfly(n: usize) -> usize {
+    N_BUTTERFLIES * n.trailing_zeros() as usize + 1
+}
+
+/// Butterfly networks require `n` to be a power of two. As the number of
+/// columns grows with `n`, we use the smallest power of two for which
+/// `nL(k + 2)` covers the space requirement.
+fn calc_n_butterfly(space: usize) -> usize {
+    let mut n = MIN_N;
+    while n * DIGEST_LENGTH * (calc_k_butterfly(n) + 2) < space {
+        n *= 2;
+    }
+    n
+}
+
 /// Minimizing delta allows us to keep our challenge size small. We minimize
 /// delta using the inequality: `n - n/delta > n/4`.
 fn calc_min_delta(n: usize) -> f32 {
@@ -86,36 +173,312 @@ fn calc_l0(k: usize, delta: f32) -> usize {
     (ln2 * delta * k_pow_2).ceil() as usize
 }
 
+/// The `l0` derivation above relies on the stacked expander's expansion. For
+/// butterfly graphs we only use the spot-check bound: a uniform challenge of
+/// `l0` labels misses a discarded `DETECTED_FRACTION` of the labels with
+/// probability `(1 - e)^l0 <= 2^-lambda`, so
+/// `l0 = ceil(lambda * ln(2) / -ln(1 - e))` (49 for `lambda = 20`). The bound
+/// does not depend on `n` or `k`, and only says that a challenge hits a
+/// discarded label, not how cheaply the Prover recomputes it; without a
+/// deadline (see `ProtoParams::response_deadline`), butterfly parameters do
+/// not prove that the Prover stores `space` bytes.
+fn calc_spot_check_l0() -> usize {
+    let lambda = SPOT_CHECK_BITS as f64;
+    let e = DETECTED_FRACTION;
+    (lambda * 2.0f64.ln() / -(1.0 - e).ln()).ceil() as usize
+}
+
+/// The challenge size of uniform sampling for a graph in `family`. A DRSample
+/// graph contains the stacked expander on its `k` columns (the DRSample edges
+/// only add parents within a column, and adding edges never makes a graph
+/// cheaper to pebble), so the expander's bound carries over to it.
+fn calc_family_l0(family: GraphFamily, k: usize, delta: f32) -> usize {
+    match family {
+        GraphFamily::StackedExpander | GraphFamily::DrSample => {
+            calc_l0(k, delta)
+        }
+        GraphFamily::Butterfly => calc_spot_check_l0(),
+    }
+}
+
+/// Adjusts the challenge size `l0` of uniform sampling for `distribution`, so
+/// that a challenge misses an adversary's discarded labels with probability
+/// at most `(1 - e)^l0`, where `e` is `DETECTED_FRACTION`. The result is
+/// capped at the number of labels the distribution can challenge.
+///
+/// - Stratified: if a fraction `e_c` of column `c` is discarded, a challenge
+///   of `m` labels per column misses them with probability at most
+///   `prod (1 - e_c)^m`, which (as `ln(1 - x)` is concave) is largest when
+///   every `e_c = e`: `(1 - e)^(m * k)`. Taking `m = ceil(l0 / k)` suffices.
+/// - DeepWeighted: the lightest `e` fraction of the labels (those in the
+///   first columns) carries at least `e^2` of the probability mass, so each
+///   draw misses them with probability at most `1 - e^2`, and
+///   `l0 * ln(1 - e) / ln(1 - e^2)` draws are needed.
+/// - LastColumn: the bound only holds for the final column's `n` labels (see
+///   `ProtoParams::proven_space`), which are challenged uniformly.
+fn calc_distributed_l0(
+    l0: usize,
+    n: usize,
+    k: usize,
+    distribution: ChallengeDistribution,
+) -> usize {
+    let e = DETECTED_FRACTION;
+    match distribution {
+        ChallengeDistribution::Uniform => l0,
+        ChallengeDistribution::LastColumn => l0.min(n),
+        ChallengeDistribution::Stratified => l0.div_ceil(k).min(n) * k,
+        ChallengeDistribution::DeepWeighted => {
+            let scale = (1.0 - e).ln() / (1.0 - e * e).ln();
+            ((l0 as f64 * scale).ceil() as usize).min(n * k)
+        }
+    }
+}
+
+/// The time an honest Prover needs to answer a challenge, apart from
+/// computing labels.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ResponseAllowance {
+    /// The time to look up one challenged label and open its Merkle path,
+    /// including recomputing the subtree below the lowest cached layer if the
+    /// Prover caches only the top layers of its tree.
+    pub opening_time: Duration,
+    /// The time to send the challenge and receive the proofs.
+    pub round_trip: Duration,
+}
+
 /// The Proof-of-Space protocol parameters.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct ProtoParams {
     pub space: usize,
     pub n: usize,
     pub k: usize,
     pub delta: f32,
     pub l0: usize,
+    pub arity: Arity,
+    pub family: GraphFamily,
+    pub labeling: LabelingMode,
+    pub distribution: ChallengeDistribution,
+    /// If set, expander graphs are resampled until their spectral gap (see
+    /// `Edges::spectral_gap`) is at least this large, and the Verifier
+    /// rejects graphs whose gap is smaller.
+    pub min_spectral_gap: Option<f64>,
 }
 
 impl ProtoParams {
-    pub fn new(space: Space) -> Self {
-        let space = space.n_bytes();
+    pub fn new(space: Space) -> Result<Self> {
+        let space = space.n_bytes()?;
 
         if space < MIN_SPACE {
-            panic!("space requirement is too small");
+            return Err(Error::Params(ParamsError::SpaceTooSmall {
+                space,
+                min_space: MIN_SPACE,
+            }));
         }
 
-        let n = calc_n(space);
+        Ok(ProtoParams::derive(
+            space,
+            GraphFamily::default(),
+            Arity::default(),
+        ))
+    }
+
+    fn derive(space: usize, family: GraphFamily, arity: Arity) -> Self {
+        let (n, k) = match family {
+            GraphFamily::StackedExpander => (calc_n(space, K), K),
+            GraphFamily::DrSample => (calc_n(space, K_DRSAMPLE), K_DRSAMPLE),
+            GraphFamily::Butterfly => {
+                let n = calc_n_butterfly(space);
+                (n, calc_k_butterfly(n))
+            }
+        };
         let delta = calc_min_delta(n);
-        let l0 = calc_l0(K, delta);
+        let l0 = calc_family_l0(family, k, delta);
 
         ProtoParams {
             space,
             n,
-            k: K,
+            k,
             delta,
             l0,
+            arity,
+            family,
+            labeling: LabelingMode::default(),
+            distribution: ChallengeDistribution::default(),
+            min_spectral_gap: None,
+        }
+    }
+
+    /// Checks that parameters that were not derived by `ProtoParams::new`
+    /// (e.g. ones read from untrusted storage) describe a graph that
+    /// challenges can be sampled from: it must have at least one vertex per
+    /// column and one column, and its number of labels must fit in a
+    /// `usize`.
+    pub fn validate(&self) -> Result<()> {
+        if self.k == 0 {
+            return Err(Error::Params(ParamsError::TooFewColumns {
+                k: self.k,
+                min_k: 1,
+            }));
+        }
+        if self.n == 0 {
+            return Err(Error::Params(ParamsError::TooFewVertices {
+                n: self.n,
+                min_n: 1,
+            }));
+        }
+        // `DeepWeighted` challenges weight the columns by `1..=k`.
+        let n_labels = self.n.checked_mul(self.k);
+        let total_weight =
+            self.k.checked_add(1).and_then(|k| k.checked_mul(self.k));
+        if n_labels.is_none() || total_weight.is_none() {
+            return Err(Error::Params(ParamsError::SizeOverflow));
+        }
+        Ok(())
+    }
+
+    /// Sets the arity of the Prover's Merkle tree.
+    pub fn with_arity(mut self, arity: Arity) -> Self {
+        self.arity = arity;
+        self
+    }
+
+    /// Sets the Prover's graph family, rederiving the graph's dimensions and
+    /// the challenge size for the same space requirement.
+    pub fn with_family(self, family: GraphFamily) -> Self {
+        ProtoParams {
+            labeling: self.labeling,
+            min_spectral_gap: self.min_spectral_gap,
+            ..ProtoParams::derive(self.space, family, self.arity)
+        }
+        .with_distribution(self.distribution)
+    }
+
+    /// Sets whether each vertex also depends on the previous vertex in its
+    /// column.
+    pub fn with_labeling(mut self, labeling: LabelingMode) -> Self {
+        self.labeling = labeling;
+        self
+    }
+
+    /// Requires sampled expander graphs to have a spectral gap of at least
+    /// `min_spectral_gap`, rejecting unluckily sampled graphs that expand
+    /// poorly. Sampling fails if no graph reaches the threshold.
+    pub fn with_min_spectral_gap(mut self, min_spectral_gap: f64) -> Self {
+        self.min_spectral_gap = Some(min_spectral_gap);
+        self
+    }
+
+    /// Sets how challenges are spread over the graph's columns, recomputing
+    /// the challenge size that the distribution needs.
+    pub fn with_distribution(
+        self,
+        distribution: ChallengeDistribution,
+    ) -> Self {
+        let l0 = calc_distributed_l0(
+            calc_family_l0(self.family, self.k, self.delta),
+            self.n,
+            self.k,
+            distribution,
+        );
+        ProtoParams { l0, distribution, ..self }
+    }
+
+    /// The space (in bytes) that a Prover answering challenges sampled for
+    /// these parameters proves that it stores. Under `LastColumn` challenges
+    /// this is only the final column's labels.
+    pub fn proven_space(&self) -> usize {
+        match self.distribution {
+            ChallengeDistribution::LastColumn => {
+                (self.n * DIGEST_LENGTH).min(self.space)
+            }
+            _ => self.space,
         }
     }
+
+    /// The miss probability that the challenge size was chosen for: a
+    /// challenge misses every discarded label of a Prover that discarded
+    /// `DETECTED_FRACTION` of them with probability at most
+    /// `(1 - DETECTED_FRACTION)^l0`, where `l0` is the challenge size of
+    /// uniform sampling (which the other distributions are sized to match).
+    /// Under `LastColumn` challenges only the final column's `n` labels
+    /// count.
+    pub fn miss_bound(&self) -> f64 {
+        let l0 = calc_family_l0(self.family, self.k, self.delta);
+        let l0 = match self.distribution {
+            ChallengeDistribution::LastColumn => l0.min(self.n),
+            _ => l0,
+        };
+        let l0 = i32::try_from(l0).unwrap_or(i32::MAX);
+        (1.0 - DETECTED_FRACTION).powi(l0)
+    }
+
+    /// A lower bound on the number of labels that a Prover which discarded
+    /// `DETECTED_FRACTION` of the labels must compute to answer a challenge.
+    /// The challenge size is chosen so that, with overwhelming probability,
+    /// the challenge includes at least one discarded label, which the Prover
+    /// must recompute along with any of its ancestors that it also discarded.
+    ///
+    /// The Prover can choose which labels to discard. Under `LastColumn`
+    /// challenges only the final column is proven, so the Prover need not
+    /// store any of a challenged label's ancestors: each of the `k - 1`
+    /// ancestor columns holds at least `IN_DEGREE` of them in an expander
+    /// graph, and `min(2^j, n)` of them `j` columns back in a butterfly graph.
+    /// Under the other distributions the Prover can discard whole columns
+    /// whose parents it stores (or part of the first column, which is
+    /// computed from the nonce alone), so the bound is a single label.
+    pub fn recomputation_cost(&self) -> usize {
+        let col = match self.distribution {
+            ChallengeDistribution::LastColumn => self.k.saturating_sub(1),
+            _ => 0,
+        };
+        let ancestors: usize = match self.family {
+            GraphFamily::StackedExpander | GraphFamily::DrSample => {
+                col * IN_DEGREE
+            }
+            GraphFamily::Butterfly => (1..=col)
+                .map(|j| {
+                    let n_ancestors = 1usize.checked_shl(j as u32);
+                    n_ancestors.map_or(self.n, |n_ancestors| {
+                        n_ancestors.min(self.n)
+                    })
+                })
+                .sum(),
+        };
+        1 + ancestors
+    }
+
+    /// The time within which the Prover must answer a challenge, given the
+    /// time it takes to compute one label: the time an honest Prover needs to
+    /// open every challenged label (see `ResponseAllowance`), plus the time
+    /// to compute `recomputation_cost()` labels. A Prover that discarded
+    /// `DETECTED_FRACTION` of its labels needs at least the latter on top of
+    /// the former.
+    ///
+    /// The deadline only tells honest and cheating Provers apart when the
+    /// recomputation takes longer than the allowance can vary by, e.g. under
+    /// `LastColumn` challenges, where a challenged label has `(k - 1) *
+    /// IN_DEGREE` ancestors, or with a slow labeling function. Under the
+    /// other distributions the recomputation is a single label, so a deadline
+    /// only catches Provers that recompute far more than they must.
+    pub fn response_deadline(
+        &self,
+        label_time: Duration,
+        allowance: ResponseAllowance,
+    ) -> Duration {
+        let times = |time: Duration, n: usize| {
+            time.checked_mul(u32::try_from(n).ok()?)
+        };
+        let deadline = || {
+            allowance
+                .round_trip
+                .checked_add(times(
+                    allowance.opening_time,
+                    challenge::challenge_len(self),
+                )?)?
+                .checked_add(times(label_time, self.recomputation_cost())?)
+        };
+        deadline().unwrap_or(Duration::MAX)
+    }
 }
 
 /*
@@ -217,3 +580,151 @@ mod pops {
     }
 }
 */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The smallest probability mass that an `e` fraction of the labels
+    /// carries under `DeepWeighted` challenges (the labels of the first
+    /// columns).
+    fn lightest_mass(k: usize, e: f64) -> f64 {
+        let total_weight = (k * (k + 1) / 2) as f64;
+        let mut n_cols = e * k as f64;
+        let mut mass = 0.0;
+        for col in 0..k {
+            let fraction = n_cols.min(1.0);
+            mass += fraction * (col + 1) as f64 / total_weight;
+            n_cols -= fraction;
+        }
+        mass
+    }
+
+    #[test]
+    fn distributed_challenges_are_as_sound_as_uniform_ones() {
+        let e = DETECTED_FRACTION;
+        for family in &[GraphFamily::StackedExpander, GraphFamily::DrSample] {
+            let params = ProtoParams::new(Space::Mbs(1))
+                .unwrap()
+                .with_family(*family);
+            let uniform_miss = (1.0 - e).powi(params.l0 as i32);
+
+            let deep = params
+                .clone()
+                .with_distribution(ChallengeDistribution::DeepWeighted);
+            let deep_miss =
+                (1.0 - lightest_mass(params.k, e)).powi(deep.l0 as i32);
+            assert!(deep.l0 > params.l0);
+            assert!(deep_miss <= uniform_miss);
+
+            let stratified = params
+                .clone()
+                .with_distribution(ChallengeDistribution::Stratified);
+            assert!(stratified.l0 >= params.l0);
+        }
+    }
+
+    #[test]
+    fn challenge_sizes_follow_each_familys_bound() {
+        for space in &[Space::Kbs(64), Space::Mbs(1), Space::Mbs(16)] {
+            let n_bytes = space.n_bytes().unwrap();
+            let params = ProtoParams::new(Space::Bytes(n_bytes)).unwrap();
+
+            let drsample = params.clone().with_family(GraphFamily::DrSample);
+            assert_eq!(drsample.k, K_DRSAMPLE);
+            assert_eq!(drsample.n, calc_n(n_bytes, K_DRSAMPLE));
+            assert_eq!(drsample.l0, calc_l0(drsample.k, drsample.delta));
+
+            let butterfly = params.with_family(GraphFamily::Butterfly);
+            assert_eq!(butterfly.k, calc_k_butterfly(butterfly.n));
+            assert_eq!(butterfly.l0, calc_spot_check_l0());
+            let miss =
+                (1.0 - DETECTED_FRACTION).powi(butterfly.l0 as i32);
+            assert!(miss <= 0.5f64.powi(SPOT_CHECK_BITS as i32));
+            let miss =
+                (1.0 - DETECTED_FRACTION).powi(butterfly.l0 as i32 - 1);
+            assert!(miss > 0.5f64.powi(SPOT_CHECK_BITS as i32));
+        }
+    }
+
+    #[test]
+    fn last_column_challenges_prove_only_the_last_column() {
+        let params = ProtoParams::new(Space::Mbs(1)).unwrap();
+        assert_eq!(params.proven_space(), params.space);
+        let params =
+            params.with_distribution(ChallengeDistribution::LastColumn);
+        assert_eq!(params.proven_space(), params.n * DIGEST_LENGTH);
+    }
+
+    #[test]
+    fn recomputation_cost_counts_the_cheapest_labels_ancestors() {
+        let params = ProtoParams::new(Space::Mbs(1)).unwrap();
+        // A first-column label is computed from the nonce alone.
+        assert_eq!(params.recomputation_cost(), 1);
+
+        let last = params
+            .clone()
+            .with_distribution(ChallengeDistribution::LastColumn);
+        let cost = 1 + (params.k - 1) * IN_DEGREE;
+        assert_eq!(last.recomputation_cost(), cost);
+
+        let butterfly = last.with_family(GraphFamily::Butterfly);
+        let log2_n = butterfly.n.trailing_zeros() as usize;
+        // `k - 1` ancestor columns: the first `log2(n)` of them double the
+        // ancestors and the rest contain all `n` vertices.
+        let cost = 1
+            + (1..=log2_n).map(|j| 1 << j).sum::<usize>()
+            + (butterfly.k - 1 - log2_n) * butterfly.n;
+        assert_eq!(butterfly.recomputation_cost(), cost);
+    }
+
+    #[test]
+    fn deadlines_allow_for_an_honest_response() {
+        let params = ProtoParams::new(Space::Mbs(1))
+            .unwrap()
+            .with_distribution(ChallengeDistribution::LastColumn);
+        let label_time = Duration::from_micros(3);
+        let allowance = ResponseAllowance {
+            opening_time: Duration::from_micros(20),
+            round_trip: Duration::from_millis(40),
+        };
+        let n_openings = challenge::challenge_len(&params) as u32;
+        let n_recomputed = params.recomputation_cost() as u32;
+        assert_eq!(
+            params.response_deadline(label_time, allowance),
+            allowance.round_trip
+                + allowance.opening_time * n_openings
+                + label_time * n_recomputed
+        );
+
+        let forever = ResponseAllowance {
+            opening_time: Duration::MAX,
+            ..allowance
+        };
+        let deadline = params.response_deadline(label_time, forever);
+        assert_eq!(deadline, Duration::MAX);
+    }
+
+    #[test]
+    fn oversized_parameters_are_rejected() {
+        let overflow = Error::Params(ParamsError::SizeOverflow).to_string();
+        let space = Space::Gbs(usize::MAX / 2);
+        assert_eq!(space.n_bytes().unwrap_err().to_string(), overflow);
+        let err = ProtoParams::new(space).unwrap_err();
+        assert_eq!(err.to_string(), overflow);
+
+        let params = ProtoParams::new(Space::Kbs(8)).unwrap();
+        assert!(params.validate().is_ok());
+        let mut too_many_labels = params.clone();
+        too_many_labels.n = usize::MAX / 2;
+        let err = too_many_labels.validate().unwrap_err();
+        assert_eq!(err.to_string(), overflow);
+
+        let mut no_columns = params.clone();
+        no_columns.k = 0;
+        assert!(no_columns.validate().is_err());
+        let mut no_vertices = params;
+        no_vertices.n = 0;
+        assert!(no_vertices.validate().is_err());
+    }
+}
@@ -0,0 +1,361 @@
+//! A pebbling game on the Prover's graph, used to estimate how much space an
+//! adversary can save on a given graph.
+//!
+//! An adversary stores (keeps a pebble on) only some of the graph's labels
+//! and recomputes the rest when they are challenged. Each `Strategy` picks the
+//! labels to store; `attack` then measures how many labels must be recomputed
+//! to answer a challenged label and how many pebbles are held at once while
+//! doing so, and compares the stored labels with the space claimed by
+//! `ProtoParams`.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use rand::Rng;
+
+use crate::graph::Edges;
+use crate::hasher::DIGEST_LENGTH;
+use crate::params::ProtoParams;
+
+/// A heuristic for choosing which labels to store.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Strategy {
+    /// Repeatedly removes (stores) the vertex with the highest remaining
+    /// degree until `n_stored` vertices are stored.
+    GreedyHighDegree { n_stored: usize },
+    /// Stores every `stride`-th column (the last column of each group of
+    /// `stride` columns) and recomputes the columns in between.
+    LayerByLayer { stride: usize },
+    /// Stores a vertex whenever the longest path of unstored vertices ending
+    /// at it would exceed `max_depth`. (Without in-column parents every
+    /// vertex in a column has the same depth, so this stores whole columns.)
+    DepthReducing { max_depth: usize },
+}
+
+/// The cost of a pebbling strategy on a graph.
+#[derive(Debug)]
+pub struct PebblingReport {
+    pub strategy: Strategy,
+    /// The number of labels in the graph.
+    pub n_labels: usize,
+    /// The number of labels the adversary stores.
+    pub n_stored: usize,
+    /// The number of bytes used by the stored labels.
+    pub stored_bytes: usize,
+    /// The space (in bytes) that `ProtoParams` claims the Prover proves it
+    /// uses (see `ProtoParams::proven_space`).
+    pub claimed_bytes: usize,
+    /// The mean number of labels recomputed to answer a uniformly sampled
+    /// challenged label.
+    pub mean_recomputed_labels: f64,
+    /// The largest number of labels recomputed for a sampled label.
+    pub max_recomputed_labels: usize,
+    /// The largest number of pebbles (stored labels plus labels held while
+    /// recomputing) in play at once.
+    pub peak_pebbles: usize,
+}
+
+/// The graph as a DAG over its `n * k` vertices, where the vertex `(col,
+/// vertex)` has the index `col * n + vertex`.
+struct StackedGraph {
+    n: usize,
+    parents: Vec<Vec<usize>>,
+    children: Vec<Vec<usize>>,
+}
+
+impl StackedGraph {
+    fn new(edges: &Edges, k: usize) -> Self {
+        let n = edges.n();
+        let mut parents = vec![vec![]; n * k];
+        let mut children = vec![vec![]; n * k];
+        for (index, vertex_parents) in parents.iter_mut().enumerate() {
+            for (parent_col, parent) in edges.parents(index / n, index % n) {
+                vertex_parents.push(parent_col * n + parent);
+                children[parent_col * n + parent].push(index);
+            }
+        }
+        StackedGraph {
+            n,
+            parents,
+            children,
+        }
+    }
+
+    fn n(&self) -> usize {
+        self.n
+    }
+
+    fn n_vertices(&self) -> usize {
+        self.parents.len()
+    }
+
+    fn parents(&self, index: usize) -> &[usize] {
+        &self.parents[index]
+    }
+
+    fn children(&self, index: usize) -> &[usize] {
+        &self.children[index]
+    }
+
+    /// The unstored vertices that must be recomputed (in index order, which
+    /// is a topological order) to derive the label of `index`.
+    fn recompute_closure(&self, stored: &[bool], index: usize) -> Vec<usize> {
+        let mut closure = HashSet::new();
+        let mut stack = vec![index];
+        while let Some(index) = stack.pop() {
+            if stored[index] || !closure.insert(index) {
+                continue;
+            }
+            stack.extend(self.parents(index).iter().cloned());
+        }
+        let mut closure: Vec<usize> = closure.into_iter().collect();
+        closure.sort_unstable();
+        closure
+    }
+
+    /// The largest number of recomputed labels held at once while
+    /// recomputing `closure` in order, where each label is discarded after
+    /// its last use.
+    fn peak_transient_pebbles(&self, closure: &[usize]) -> usize {
+        let in_closure: HashSet<usize> = closure.iter().cloned().collect();
+        let mut last_use: HashMap<usize, usize> =
+            closure.iter().map(|index| (*index, *index)).collect();
+        for index in closure {
+            for parent in self.parents(*index) {
+                if in_closure.contains(parent) {
+                    last_use.insert(*parent, *index);
+                }
+            }
+        }
+
+        let mut n_freed_at: HashMap<usize, usize> = HashMap::new();
+        for last in last_use.values() {
+            *n_freed_at.entry(*last).or_insert(0) += 1;
+        }
+
+        let (mut n_live, mut peak) = (0, 0);
+        for index in closure {
+            n_live += 1;
+            peak = peak.max(n_live);
+            n_live -= n_freed_at.get(index).cloned().unwrap_or(0);
+        }
+        peak
+    }
+}
+
+/// Returns which of the `n * k` labels `strategy` stores.
+pub fn stored_labels(edges: &Edges, k: usize, strategy: Strategy) -> Vec<bool> {
+    let graph = StackedGraph::new(edges, k);
+    let n = graph.n();
+    let n_vertices = graph.n_vertices();
+
+    match strategy {
+        Strategy::GreedyHighDegree { n_stored } => {
+            let mut stored = vec![false; n_vertices];
+            let mut degrees: Vec<usize> = (0..n_vertices)
+                .map(|index| {
+                    graph.parents(index).len() + graph.children(index).len()
+                })
+                .collect();
+            let mut heap: BinaryHeap<(usize, Reverse<usize>)> = degrees
+                .iter()
+                .enumerate()
+                .map(|(index, degree)| (*degree, Reverse(index)))
+                .collect();
+
+            let mut n_removed = 0;
+            while n_removed < n_stored.min(n_vertices) {
+                let (degree, Reverse(index)) = match heap.pop() {
+                    Some(entry) => entry,
+                    None => break,
+                };
+                if stored[index] || degree != degrees[index] {
+                    continue;
+                }
+                stored[index] = true;
+                n_removed += 1;
+                let neighbors =
+                    graph.parents(index).iter().chain(graph.children(index));
+                for &neighbor in neighbors {
+                    if !stored[neighbor] {
+                        degrees[neighbor] -= 1;
+                        heap.push((degrees[neighbor], Reverse(neighbor)));
+                    }
+                }
+            }
+            stored
+        }
+        Strategy::LayerByLayer { stride } => {
+            let stride = stride.max(1);
+            (0..n_vertices)
+                .map(|index| (index / n + 1).is_multiple_of(stride))
+                .collect()
+        }
+        Strategy::DepthReducing { max_depth } => {
+            let max_depth = max_depth.max(1);
+            let mut stored = vec![false; n_vertices];
+            let mut depths = vec![0; n_vertices];
+            for index in 0..n_vertices {
+                let depth = 1 + graph
+                    .parents(index)
+                    .iter()
+                    .map(|parent| depths[*parent])
+                    .max()
+                    .unwrap_or(0);
+                if depth > max_depth {
+                    stored[index] = true;
+                } else {
+                    depths[index] = depth;
+                }
+            }
+            stored
+        }
+    }
+}
+
+/// Runs `strategy` on the graph described by `params` and `edges`, sampling
+/// `n_samples` challenged labels to estimate the recomputation cost.
+pub fn attack<R: Rng>(
+    params: &ProtoParams,
+    edges: &Edges,
+    strategy: Strategy,
+    n_samples: usize,
+    rng: &mut R,
+) -> PebblingReport {
+    let graph = StackedGraph::new(edges, params.k);
+    let stored = stored_labels(edges, params.k, strategy);
+    let n_labels = graph.n_vertices();
+    let n_stored = stored.iter().filter(|stored| **stored).count();
+    // An empty graph has no labels to challenge.
+    let n_samples = if n_labels == 0 { 0 } else { n_samples };
+
+    let mut total_recomputed = 0;
+    let mut max_recomputed = 0;
+    let mut peak_transient = 0;
+
+    for _ in 0..n_samples {
+        let index = rng.gen_range(0, n_labels);
+        let closure = graph.recompute_closure(&stored, index);
+        total_recomputed += closure.len();
+        max_recomputed = max_recomputed.max(closure.len());
+        peak_transient =
+            peak_transient.max(graph.peak_transient_pebbles(&closure));
+    }
+
+    PebblingReport {
+        strategy,
+        n_labels,
+        n_stored,
+        stored_bytes: n_stored * DIGEST_LENGTH,
+        claimed_bytes: params.proven_space(),
+        mean_recomputed_labels: total_recomputed as f64
+            / n_samples.max(1) as f64,
+        max_recomputed_labels: max_recomputed,
+        peak_pebbles: n_stored + peak_transient,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::graph::{GraphFamily, LabelingMode};
+    use crate::params::Space;
+
+    fn params_and_edges(family: GraphFamily) -> (ProtoParams, Edges) {
+        let params =
+            ProtoParams::new(Space::Kbs(16)).unwrap().with_family(family);
+        let mut rng = StdRng::seed_from_u64(0);
+        let edges = Edges::from_params_with_rng(&params, &mut rng).unwrap();
+        (params, edges)
+    }
+
+    /// The length of the longest path of unstored vertices ending at each
+    /// vertex.
+    fn unstored_depths(graph: &StackedGraph, stored: &[bool]) -> Vec<usize> {
+        let mut depths = vec![0; graph.n_vertices()];
+        for index in 0..graph.n_vertices() {
+            if !stored[index] {
+                depths[index] = 1 + graph
+                    .parents(index)
+                    .iter()
+                    .map(|parent| depths[*parent])
+                    .max()
+                    .unwrap_or(0);
+            }
+        }
+        depths
+    }
+
+    #[test]
+    fn storing_every_column_recomputes_nothing() {
+        let (params, edges) = params_and_edges(GraphFamily::StackedExpander);
+        let strategy = Strategy::LayerByLayer { stride: 1 };
+        let mut rng = StdRng::seed_from_u64(1);
+        let report = attack(&params, &edges, strategy, 100, &mut rng);
+        assert_eq!(report.n_stored, params.n * params.k);
+        assert_eq!(report.n_stored, report.n_labels);
+        assert_eq!(report.mean_recomputed_labels, 0.0);
+        assert_eq!(report.max_recomputed_labels, 0);
+        assert_eq!(report.peak_pebbles, report.n_labels);
+    }
+
+    #[test]
+    fn depth_reducing_respects_max_depth() {
+        for family in &[GraphFamily::StackedExpander, GraphFamily::DrSample] {
+            let (params, edges) = params_and_edges(*family);
+            let edges = edges.with_labeling(LabelingMode::Localized);
+            let graph = StackedGraph::new(&edges, params.k);
+            for max_depth in &[1, 2, 5, 40] {
+                let strategy = Strategy::DepthReducing {
+                    max_depth: *max_depth,
+                };
+                let stored = stored_labels(&edges, params.k, strategy);
+                let depths = unstored_depths(&graph, &stored);
+                assert!(depths.iter().all(|depth| depth <= max_depth));
+                assert!(depths.contains(max_depth));
+            }
+        }
+    }
+
+    #[test]
+    fn greedy_high_degree_stores_exactly_n_stored() {
+        let (params, edges) = params_and_edges(GraphFamily::Butterfly);
+        let n_labels = params.n * params.k;
+        for n_stored in &[0, 1, 17, n_labels / 2, n_labels, n_labels + 1] {
+            let strategy = Strategy::GreedyHighDegree {
+                n_stored: *n_stored,
+            };
+            let stored = stored_labels(&edges, params.k, strategy);
+            let n_actual = stored.iter().filter(|stored| **stored).count();
+            assert_eq!(n_actual, (*n_stored).min(n_labels));
+        }
+    }
+
+    #[test]
+    fn peak_transient_pebbles_counts_live_labels() {
+        // 0 -> 1 -> 3 and 0 -> 2 -> 3: label 0 stays live until 2 is
+        // computed, and 1 and 2 until 3 is.
+        let parents = vec![vec![], vec![0], vec![0], vec![1, 2]];
+        let mut children = vec![vec![]; parents.len()];
+        for (index, vertex_parents) in parents.iter().enumerate() {
+            for parent in vertex_parents {
+                children[*parent].push(index);
+            }
+        }
+        let graph = StackedGraph { n: 4, parents, children };
+        assert_eq!(graph.peak_transient_pebbles(&[0, 1, 2, 3]), 3);
+        // Stored parents are not pebbles held while recomputing.
+        assert_eq!(graph.peak_transient_pebbles(&[1, 2, 3]), 3);
+        assert_eq!(graph.peak_transient_pebbles(&[2, 3]), 2);
+        assert_eq!(graph.peak_transient_pebbles(&[3]), 1);
+        assert_eq!(graph.peak_transient_pebbles(&[]), 0);
+
+        let stored = [true, false, false, false];
+        assert_eq!(graph.recompute_closure(&stored, 3), vec![1, 2, 3]);
+        let stored = [false, false, false, true];
+        assert!(graph.recompute_closure(&stored, 3).is_empty());
+    }
+}
@@ -1,28 +1,173 @@
+use serde::{Deserialize, Serialize};
+
+use crate::challenge::{self, ChallengeError, ChallengeSource, PublicProof};
+use crate::error::{Error, Result};
 use crate::graph::{Edges, LabelMatrix};
-use crate::merkle::{MerkleLabel, MerkleProof, MerkleTree};
-use crate::params::ProtoParams;
+use crate::hasher::Hasher;
+use crate::merkle::{MerkleError, MerkleLabel, MerkleProof, MerkleTree};
+use crate::params::{ParamsError, ProtoParams};
+
+/// Everything the Verifier needs to know about the Prover's graph in order to
+/// challenge it.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Commitment {
+    pub params: ProtoParams,
+    pub nonce: Vec<u8>,
+    /// The identity that the Prover claims its labels are keyed by.
+    pub prover_id: Vec<u8>,
+    pub edges: Edges,
+    pub merkle_root: MerkleLabel,
+}
 
-#[derive(Debug)]
+impl Commitment {
+    /// The digest that challenges derived from the commitment are seeded
+    /// with.
+    pub fn digest(&self) -> Result<Vec<u8>> {
+        Hasher::new().commitment_digest(self)
+    }
+}
+
+/// The digest of the commitment made up of the given parts, without cloning
+/// them into a `Commitment`. (A tuple of the fields serializes exactly like
+/// the struct.)
+pub(crate) fn commitment_digest(
+    params: &ProtoParams,
+    nonce: &[u8],
+    prover_id: &[u8],
+    edges: &Edges,
+    merkle_root: &MerkleLabel,
+) -> Result<Vec<u8>> {
+    Hasher::new()
+        .commitment_digest(&(params, nonce, prover_id, edges, merkle_root))
+}
+
+/// Fails if `edges` are malformed or were not sampled for the graph family and
+/// number of vertices per column in `params`, or if `params` are invalid.
+pub(crate) fn check_graph(params: &ProtoParams, edges: &Edges) -> Result<()> {
+    params.validate()?;
+    if edges.family() != params.family {
+        return Err(Error::Params(ParamsError::GraphFamilyMismatch {
+            expected: params.family,
+            actual: edges.family(),
+        }));
+    }
+    if edges.n() != params.n {
+        return Err(Error::Params(ParamsError::GraphSizeMismatch {
+            expected: params.n,
+            actual: edges.n(),
+        }));
+    }
+    edges.validate()
+}
+
+#[derive(Debug, Deserialize, Serialize)]
 pub struct Prover {
     params: ProtoParams,
+    nonce: Vec<u8>,
+    prover_id: Vec<u8>,
     edges: Edges,
     label_matrix: LabelMatrix,
     merkle_tree: MerkleTree,
 }
 
 impl Prover {
-    pub fn new(params: ProtoParams, nonce: Vec<u8>) -> Self {
-        let edges = Edges::new_permutation(params.n);
-        let label_matrix = LabelMatrix::new(&edges, params.k, &nonce);
-        let merkle_tree = MerkleTree::from_label_matrix(&label_matrix);
+    /// Creates a Prover whose labels are keyed by `prover_id` (e.g. its
+    /// public key bytes); only a Verifier expecting `prover_id` accepts its
+    /// proofs.
+    pub fn new(
+        params: ProtoParams,
+        nonce: Vec<u8>,
+        prover_id: Vec<u8>,
+    ) -> Result<Self> {
+        Prover::with_cached_layers(params, nonce, prover_id, usize::MAX)
+    }
+
+    /// Creates a Prover that keeps only the top `n_cached_layers` layers of
+    /// its Merkle tree in memory, trading the memory used by the tree for
+    /// recomputing the lower layers when a challenge is answered.
+    pub fn with_cached_layers(
+        params: ProtoParams,
+        nonce: Vec<u8>,
+        prover_id: Vec<u8>,
+        n_cached_layers: usize,
+    ) -> Result<Self> {
+        let edges = Edges::from_params(&params)?;
+        Prover::from_edges(params, nonce, prover_id, edges, n_cached_layers)
+    }
+
+    /// Creates a Prover for a graph whose edges were sampled by the caller
+    /// (e.g. using `Edges::new_with_rng` and a seeded RNG).
+    pub fn from_edges(
+        params: ProtoParams,
+        nonce: Vec<u8>,
+        prover_id: Vec<u8>,
+        edges: Edges,
+        n_cached_layers: usize,
+    ) -> Result<Self> {
+        check_graph(&params, &edges)?;
+        let label_matrix =
+            LabelMatrix::new(&edges, params.k, &nonce, &prover_id);
+        let merkle_tree = MerkleTree::from_label_matrix(
+            &label_matrix,
+            params.arity,
+            n_cached_layers,
+        );
+        Ok(Prover {
+            params,
+            nonce,
+            prover_id,
+            edges,
+            label_matrix,
+            merkle_tree,
+        })
+    }
+
+    pub(crate) fn from_parts(
+        params: ProtoParams,
+        nonce: Vec<u8>,
+        prover_id: Vec<u8>,
+        edges: Edges,
+        label_matrix: LabelMatrix,
+        merkle_tree: MerkleTree,
+    ) -> Self {
         Prover {
             params,
+            nonce,
+            prover_id,
             edges,
             label_matrix,
             merkle_tree,
         }
     }
 
+    /// Checks that a deserialized Prover is consistent with its parameters.
+    /// State restored from untrusted storage must be validated before it is
+    /// used to answer challenges.
+    pub fn validate(&self) -> Result<()> {
+        check_graph(&self.params, &self.edges)?;
+        let (n, k) = (self.params.n, self.params.k);
+        let columns = &self.label_matrix.0;
+        let labels_match = columns.len() == k
+            && columns.iter().all(|col_labels| col_labels.len() == n);
+        if !labels_match {
+            return Err(MerkleError::MalformedTree.into());
+        }
+        self.merkle_tree.check(n * k, self.params.arity)?;
+        Ok(())
+    }
+
+    pub fn params(&self) -> &ProtoParams {
+        &self.params
+    }
+
+    pub fn nonce(&self) -> &[u8] {
+        &self.nonce
+    }
+
+    pub fn prover_id(&self) -> &[u8] {
+        &self.prover_id
+    }
+
     pub fn edges(&self) -> &Edges {
         &self.edges
     }
@@ -31,19 +176,69 @@ impl Prover {
         self.merkle_tree.root()
     }
 
+    pub(crate) fn label_matrix(&self) -> &LabelMatrix {
+        &self.label_matrix
+    }
+
+    pub fn commitment(&self) -> Commitment {
+        Commitment {
+            params: self.params.clone(),
+            nonce: self.nonce.clone(),
+            prover_id: self.prover_id.clone(),
+            edges: self.edges.clone(),
+            merkle_root: self.merkle_root().to_vec(),
+        }
+    }
+
+    /// Answers the Verifier's challenge. Challenges arrive over the network,
+    /// so a challenge that is the wrong size, repeats an index or names a
+    /// label outside the graph is rejected rather than answered.
     pub fn create_proofs(
         &mut self,
         challenge_indices: &[usize],
-    ) -> Vec<MerkleProof> {
+    ) -> Result<Vec<MerkleProof>> {
+        challenge::check_challenge(&self.params, challenge_indices)?;
         challenge_indices
             .iter()
             .map(|challenge_index| self.create_proof(*challenge_index))
             .collect()
     }
 
-    pub fn create_proof(&self, challenge_index: usize) -> MerkleProof {
-        let path = self.merkle_tree.open(challenge_index);
-        MerkleProof { challenge_index, path }
+    /// Answers a challenge drawn from `source` (e.g. a Fiat–Shamir or beacon
+    /// source), recording where the challenge came from.
+    pub fn create_public_proof(
+        &mut self,
+        source: &mut impl ChallengeSource,
+    ) -> Result<PublicProof> {
+        let digest = commitment_digest(
+            &self.params,
+            &self.nonce,
+            &self.prover_id,
+            &self.edges,
+            self.merkle_root(),
+        )?;
+        let challenge = source.challenge(&self.params, &digest)?;
+        Ok(PublicProof {
+            origin: challenge.origin,
+            proofs: self.create_proofs(&challenge.indices)?,
+        })
+    }
+
+    /// Opens the label at `challenge_index`. Fails for indices past the
+    /// graph's `n * k` labels, including those of the Merkle tree's padding
+    /// leaves.
+    pub fn create_proof(&self, challenge_index: usize) -> Result<MerkleProof> {
+        let n_indices = self.params.n * self.params.k;
+        if challenge_index >= n_indices {
+            return Err(Error::Challenge(ChallengeError::IndexOutOfRange {
+                index: challenge_index,
+                n_indices,
+            }));
+        }
+        let path =
+            self.merkle_tree.open(&self.label_matrix, challenge_index)?;
+        let arity = self.merkle_tree.arity();
+        Ok(MerkleProof { challenge_index, path, arity })
 
         /*
         let path = self.merkle_tree.open(challenge_index);
@@ -88,3 +283,42 @@ impl Prover {
     }
     */
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkle::Arity;
+    use crate::params::Space;
+
+    fn restored(prover: &Prover) -> Prover {
+        let bytes = bincode::serialize(prover).unwrap();
+        bincode::deserialize(&bytes).unwrap()
+    }
+
+    #[test]
+    fn corrupted_state_is_rejected() {
+        let params = ProtoParams::new(Space::Kbs(4)).unwrap();
+        let prover =
+            Prover::with_cached_layers(params, vec![1], vec![2], 2).unwrap();
+        assert!(restored(&prover).validate().is_ok());
+
+        let mut missing_labels = restored(&prover);
+        missing_labels.label_matrix.0[0].pop();
+        assert_eq!(
+            missing_labels.validate().unwrap_err().to_string(),
+            "merkle error: MalformedTree"
+        );
+
+        let mut wrong_arity = restored(&prover);
+        wrong_arity.params.arity = Arity::Octal;
+        assert!(wrong_arity.validate().is_err());
+
+        let mut wrong_size = restored(&prover);
+        wrong_size.params.n *= 2;
+        assert!(wrong_size.validate().is_err());
+
+        let mut no_columns = restored(&prover);
+        no_columns.params.k = 0;
+        assert!(no_columns.validate().is_err());
+    }
+}
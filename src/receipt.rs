@@ -0,0 +1,89 @@
+//! Signed verification receipts.
+//!
+//! A `Receipt` records the outcome of one round of verification: the
+//! parameters, nonce and Prover identity the Verifier was configured with, the
+//! Prover's Merkle root, the challenge and where it came from, the response
+//! deadline and the result. The Verifier signs it
+//! with its Ed25519 key, so a third party that trusts the Verifier's public key
+//! can accept the result without rerunning the verification.
+
+use std::time::Duration;
+
+use ed25519_dalek::{Signature, Signer, Verifier as _};
+use serde::{Deserialize, Serialize};
+
+pub use ed25519_dalek::{SigningKey, VerifyingKey};
+
+use crate::challenge::Challenge;
+use crate::error::{Error, Result};
+use crate::merkle::MerkleLabel;
+use crate::params::ProtoParams;
+use crate::verifier::VerificationResult;
+
+/// Separates receipt signatures from any other use of the Verifier's key.
+const RECEIPT_DOMAIN: &[u8] = b"pots-receipt-v2";
+
+#[derive(Debug, PartialEq)]
+pub enum ReceiptError {
+    InvalidSignature,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Receipt {
+    pub params: ProtoParams,
+    pub nonce: Vec<u8>,
+    pub prover_id: Vec<u8>,
+    pub merkle_root: MerkleLabel,
+    pub challenge: Challenge,
+    /// The deadline the Prover had to answer the challenge within, if the
+    /// Verifier enforced one.
+    pub deadline: Option<Duration>,
+    pub result: VerificationResult,
+    /// Seconds since the Unix epoch, as supplied by the Verifier.
+    pub timestamp: u64,
+    /// The Ed25519 signature over every other field.
+    pub signature: Vec<u8>,
+}
+
+impl Receipt {
+    /// Returns `true` if the receipt records a successful verification.
+    pub fn is_accepted(&self) -> bool {
+        self.result.is_ok()
+    }
+
+    /// The bytes covered by the signature.
+    fn message(&self) -> Result<Vec<u8>> {
+        let fields = (
+            &self.params,
+            &self.nonce,
+            &self.prover_id,
+            &self.merkle_root,
+            &self.challenge,
+            &self.deadline,
+            &self.result,
+            self.timestamp,
+        );
+        let mut message = RECEIPT_DOMAIN.to_vec();
+        message.extend(bincode::serialize(&fields)?);
+        Ok(message)
+    }
+
+    /// Signs the receipt with `signing_key`, replacing any existing
+    /// signature.
+    pub fn sign(&mut self, signing_key: &SigningKey) -> Result<()> {
+        let signature = signing_key.sign(&self.message()?);
+        self.signature = signature.to_bytes().to_vec();
+        Ok(())
+    }
+
+    /// Checks that the receipt was signed by the holder of `verifying_key`
+    /// and has not been altered since.
+    pub fn verify(&self, verifying_key: &VerifyingKey) -> Result<()> {
+        let invalid = |_| Error::Receipt(ReceiptError::InvalidSignature);
+        let signature =
+            Signature::from_slice(&self.signature).map_err(invalid)?;
+        verifying_key
+            .verify(&self.message()?, &signature)
+            .map_err(invalid)
+    }
+}
@@ -0,0 +1,606 @@
+//! Proofs of replication.
+//!
+//! A `Replica` encodes a customer's data by XORing each `DIGEST_LENGTH` byte
+//! block of the data with the label of the corresponding vertex in the
+//! graph's final column, and commits to the encoded blocks with a Merkle tree.
+//! The nonce acts as the replica's identifier: encoding the same data under
+//! different nonces yields independent replicas, so a Prover cannot answer for
+//! several replicas while storing only one.
+//!
+//! The customer commits to the data with the Merkle root of its blocks (comm_D,
+//! a `DataCommitment`), so the Verifier does not need a copy of the data. Each
+//! challenged block is opened in both trees: the Verifier recomputes the
+//! block's key (the final-column label), decodes the opened replica block and
+//! checks that it is the data block opened against comm_D. The Prover keeps
+//! the data's Merkle tree alongside the replica in order to open it.
+
+use std::time::Duration;
+
+use rand::rngs::OsRng;
+use rand::seq::index;
+use rand::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+
+use crate::challenge::{self, ChallengeError};
+use crate::error::{Error, Result};
+use crate::graph::{Edges, LabelMatrix, VertexLabel};
+use crate::hasher::DIGEST_LENGTH;
+use crate::clock::Clock;
+use crate::merkle::{self, Arity, MerkleLabel, MerkleProof, MerkleTree};
+use crate::params::ProtoParams;
+use crate::prover;
+use crate::verifier::{VerificationError, VerificationResult, Verifier};
+
+#[derive(Debug, PartialEq)]
+pub enum ReplicaError {
+    /// The data does not fit in the graph's final column of `n` labels.
+    DataTooLarge { max_len: usize },
+}
+
+/// Splits `data` into zero-padded blocks of `DIGEST_LENGTH` bytes (at least
+/// one block, even if `data` is empty).
+fn split_blocks(data: &[u8]) -> Vec<VertexLabel> {
+    let n_blocks = n_blocks(data.len());
+    (0..n_blocks)
+        .map(|block_index| {
+            let start = (block_index * DIGEST_LENGTH).min(data.len());
+            let end = (start + DIGEST_LENGTH).min(data.len());
+            let mut block = data[start..end].to_vec();
+            block.resize(DIGEST_LENGTH, 0);
+            block
+        })
+        .collect()
+}
+
+fn xor(block: &[u8], key: &[u8]) -> VertexLabel {
+    block.iter().zip(key).map(|(b, k)| b ^ k).collect()
+}
+
+/// The number of blocks that `data_len` bytes of data are split into.
+fn n_blocks(data_len: usize) -> usize {
+    data_len.div_ceil(DIGEST_LENGTH).max(1)
+}
+
+/// Fails if `data_len` bytes of data do not fit in the graph's final column.
+fn check_data_len(params: &ProtoParams, data_len: usize) -> Result<()> {
+    let max_len = params.n.saturating_mul(DIGEST_LENGTH);
+    if data_len > max_len {
+        return Err(Error::Replica(ReplicaError::DataTooLarge { max_len }));
+    }
+    Ok(())
+}
+
+/// Builds a Merkle tree over the blocks of `data` that stores every layer,
+/// including its leaves, so that it can be opened without the data.
+fn data_tree(data: &[u8], arity: Arity) -> MerkleTree {
+    let blocks = LabelMatrix(vec![split_blocks(data)]);
+    MerkleTree::from_label_matrix(&blocks, arity, usize::MAX)
+}
+
+/// The customer's commitment to the data (comm_D): the Merkle root of its
+/// blocks, and its length.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct DataCommitment {
+    pub data_len: usize,
+    pub data_root: MerkleLabel,
+}
+
+impl DataCommitment {
+    pub fn new(data: &[u8], arity: Arity) -> Self {
+        DataCommitment {
+            data_len: data.len(),
+            data_root: data_tree(data, arity).root().to_vec(),
+        }
+    }
+}
+
+/// Opens a challenged block in both the replica and the data.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ReplicaProof {
+    pub replica: MerkleProof,
+    pub data: MerkleProof,
+}
+
+/// Everything the Verifier needs to know about a replica in order to
+/// challenge it.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ReplicaCommitment {
+    pub params: ProtoParams,
+    pub nonce: Vec<u8>,
+    pub prover_id: Vec<u8>,
+    pub edges: Edges,
+    pub data_len: usize,
+    pub replica_root: MerkleLabel,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Replica {
+    params: ProtoParams,
+    nonce: Vec<u8>,
+    prover_id: Vec<u8>,
+    edges: Edges,
+    data_len: usize,
+    /// The encoded blocks, stored as a single-column matrix.
+    blocks: LabelMatrix,
+    merkle_tree: MerkleTree,
+    /// The Merkle tree over the data's blocks, whose root is comm_D.
+    data_tree: MerkleTree,
+}
+
+impl Replica {
+    /// Encodes `data` for the Prover `prover_id` using a newly sampled graph.
+    pub fn encode(
+        params: ProtoParams,
+        nonce: Vec<u8>,
+        prover_id: Vec<u8>,
+        data: &[u8],
+    ) -> Result<Self> {
+        let edges = Edges::from_params(&params)?;
+        Replica::from_edges(params, nonce, prover_id, edges, data)
+    }
+
+    /// Encodes `data` using a graph whose edges were sampled by the caller.
+    pub fn from_edges(
+        params: ProtoParams,
+        nonce: Vec<u8>,
+        prover_id: Vec<u8>,
+        edges: Edges,
+        data: &[u8],
+    ) -> Result<Self> {
+        prover::check_graph(&params, &edges)?;
+        check_data_len(&params, data.len())?;
+
+        let label_matrix =
+            LabelMatrix::new(&edges, params.k, &nonce, &prover_id);
+        let keys = &label_matrix.0[params.k - 1];
+        let blocks: Vec<VertexLabel> = split_blocks(data)
+            .iter()
+            .zip(keys)
+            .map(|(block, key)| xor(block, key))
+            .collect();
+        let blocks = LabelMatrix(vec![blocks]);
+        let merkle_tree =
+            MerkleTree::from_label_matrix(&blocks, params.arity, usize::MAX);
+        let data_tree = data_tree(data, params.arity);
+
+        Ok(Replica {
+            params,
+            nonce,
+            prover_id,
+            edges,
+            data_len: data.len(),
+            blocks,
+            merkle_tree,
+            data_tree,
+        })
+    }
+
+    pub fn params(&self) -> &ProtoParams {
+        &self.params
+    }
+
+    pub fn nonce(&self) -> &[u8] {
+        &self.nonce
+    }
+
+    pub fn n_blocks(&self) -> usize {
+        self.blocks.0.first().map_or(0, |blocks| blocks.len())
+    }
+
+    pub fn replica_root(&self) -> &MerkleLabel {
+        self.merkle_tree.root()
+    }
+
+    /// The commitment to the data that this replica encodes.
+    pub fn data_commitment(&self) -> DataCommitment {
+        DataCommitment {
+            data_len: self.data_len,
+            data_root: self.data_tree.root().to_vec(),
+        }
+    }
+
+    pub fn commitment(&self) -> ReplicaCommitment {
+        ReplicaCommitment {
+            params: self.params.clone(),
+            nonce: self.nonce.clone(),
+            prover_id: self.prover_id.clone(),
+            edges: self.edges.clone(),
+            data_len: self.data_len,
+            replica_root: self.replica_root().to_vec(),
+        }
+    }
+
+    /// Answers the Verifier's challenge, which must contain `l0` (or, for
+    /// smaller replicas, every) distinct block indices.
+    pub fn create_proofs(
+        &self,
+        block_indices: &[usize],
+    ) -> Result<Vec<ReplicaProof>> {
+        let n_blocks = self.n_blocks();
+        let n_challenged = self.params.l0.min(n_blocks);
+        challenge::check_indices(block_indices, n_blocks, n_challenged)?;
+        block_indices
+            .iter()
+            .map(|block_index| self.create_proof(*block_index))
+            .collect()
+    }
+
+    /// Opens the encoded block and the data block at `block_index`.
+    pub fn create_proof(&self, block_index: usize) -> Result<ReplicaProof> {
+        let n_blocks = self.n_blocks();
+        if block_index >= n_blocks {
+            return Err(Error::Challenge(ChallengeError::IndexOutOfRange {
+                index: block_index,
+                n_indices: n_blocks,
+            }));
+        }
+        let arity = self.merkle_tree.arity();
+        let replica = MerkleProof {
+            challenge_index: block_index,
+            path: self.merkle_tree.open(&self.blocks, block_index)?,
+            arity,
+        };
+        // Every layer of the data tree is stored, so no leaves are needed to
+        // open it.
+        let data = MerkleProof {
+            challenge_index: block_index,
+            path: self.data_tree.open(&LabelMatrix::empty(0), block_index)?,
+            arity,
+        };
+        Ok(ReplicaProof { replica, data })
+    }
+
+    /// Recovers the original data by recomputing the keys and decoding every
+    /// block.
+    pub fn decode(&self) -> Vec<u8> {
+        let label_matrix = LabelMatrix::new(
+            &self.edges,
+            self.params.k,
+            &self.nonce,
+            &self.prover_id,
+        );
+        let keys = label_matrix.0.last().into_iter().flatten();
+        let blocks = self.blocks.0.first().into_iter().flatten();
+        let mut data: Vec<u8> = blocks
+            .zip(keys)
+            .flat_map(|(block, key)| xor(block, key))
+            .collect();
+        data.truncate(self.data_len);
+        data
+    }
+}
+
+/// Challenges a replica of the data committed to by a `DataCommitment`. The
+/// Verifier samples its challenges using `R`, which defaults to the operating
+/// system's RNG.
+#[derive(Debug)]
+pub struct ReplicaVerifier<R = OsRng> {
+    verifier: Verifier<R>,
+    data_commitment: DataCommitment,
+    replica_root: Option<MerkleLabel>,
+    /// The outstanding challenge, if any.
+    challenge: Option<Vec<usize>>,
+}
+
+impl ReplicaVerifier {
+    pub fn new(
+        params: ProtoParams,
+        nonce: Vec<u8>,
+        prover_id: Vec<u8>,
+        data_commitment: DataCommitment,
+    ) -> Result<Self> {
+        let rng = OsRng::new()?;
+        ReplicaVerifier::with_rng(
+            params,
+            nonce,
+            prover_id,
+            data_commitment,
+            rng,
+        )
+    }
+}
+
+impl<R: RngCore + CryptoRng> ReplicaVerifier<R> {
+    /// Creates a Verifier that samples its challenges using `rng`. Fails if
+    /// the committed data does not fit in the graph's final column.
+    pub fn with_rng(
+        params: ProtoParams,
+        nonce: Vec<u8>,
+        prover_id: Vec<u8>,
+        data_commitment: DataCommitment,
+        rng: R,
+    ) -> Result<Self> {
+        check_data_len(&params, data_commitment.data_len)?;
+        Ok(ReplicaVerifier {
+            verifier: Verifier::with_rng(params, nonce, prover_id, rng)?,
+            data_commitment,
+            replica_root: None,
+            challenge: None,
+        })
+    }
+
+    /// Sets the clock used to time the Prover's responses.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.verifier = self.verifier.with_clock(clock);
+        self
+    }
+
+    /// Rejects proofs that arrive more than `deadline` after the challenge
+    /// was issued.
+    pub fn with_deadline(mut self, deadline: Duration) -> Self {
+        self.verifier = self.verifier.with_deadline(deadline);
+        self
+    }
+
+    pub fn params(&self) -> &ProtoParams {
+        self.verifier.params()
+    }
+
+    pub fn nonce(&self) -> &[u8] {
+        self.verifier.nonce()
+    }
+
+    /// The outstanding challenge, if one has been issued and not yet
+    /// answered.
+    pub fn challenge(&self) -> Option<&[usize]> {
+        self.challenge.as_deref()
+    }
+
+    pub fn set_commitment(
+        &mut self,
+        edges: Edges,
+        replica_root: MerkleLabel,
+    ) -> Result<()> {
+        self.verifier.set_graph_description(edges, replica_root.clone())?;
+        self.replica_root = Some(replica_root);
+        Ok(())
+    }
+
+    /// Samples `l0` distinct block indices (or every block, if the replica
+    /// has fewer than `l0` blocks). Replaces any outstanding challenge.
+    pub fn gen_challenge(&mut self) -> Vec<usize> {
+        let n_blocks = self.n_blocks();
+        let n_challenged = self.params().l0.min(n_blocks);
+        let challenge =
+            index::sample(self.verifier.rng_mut(), n_blocks, n_challenged)
+                .into_vec();
+        self.challenge = Some(challenge.clone());
+        self.verifier.start_deadline();
+        challenge
+    }
+
+    fn n_blocks(&self) -> usize {
+        n_blocks(self.data_commitment.data_len)
+    }
+
+    /// Checks that `proofs` answer the outstanding challenge, that each
+    /// opened block is in the committed replica and that it decodes to the
+    /// block of the committed data at the same index. Fails if no challenge
+    /// is outstanding or the deadline has passed; the challenge is consumed
+    /// whether or not verification succeeds.
+    pub fn verify_proofs(
+        &mut self,
+        proofs: &[ReplicaProof],
+    ) -> VerificationResult {
+        let challenge = self
+            .challenge
+            .take()
+            .ok_or(VerificationError::MissingChallenge)?;
+        self.verifier.check_deadline()?;
+        let indices: Vec<usize> = proofs
+            .iter()
+            .map(|proof| proof.replica.challenge_index)
+            .collect();
+        if indices != challenge {
+            return Err(VerificationError::ProofsDoNotAnswerChallenge {
+                expected: challenge,
+                actual: indices,
+            });
+        }
+        for proof in proofs {
+            self.verify_proof(proof)?;
+        }
+        Ok(())
+    }
+
+    fn verify_proof(&mut self, proof: &ReplicaProof) -> VerificationResult {
+        let n_blocks = self.n_blocks();
+        let depth = merkle::n_layers(n_blocks, self.params().arity) - 1;
+        let challenge_index = proof.replica.challenge_index;
+        self.check_shape(&proof.replica, challenge_index, depth)?;
+        self.check_shape(&proof.data, challenge_index, depth)?;
+
+        let n = self.params().n;
+        let key_index = (self.params().k - 1) * n + challenge_index;
+        let key = self.verifier.pebble_to(key_index)?;
+        let decoded = xor(&proof.replica.path[0], &key);
+        if decoded != proof.data.path[0] {
+            return Err(VerificationError::InvalidReplicaBlock {
+                challenge_index,
+                expected: proof.data.path[0].clone(),
+                actual: decoded,
+            });
+        }
+
+        let replica_root = self
+            .replica_root
+            .as_ref()
+            .ok_or(VerificationError::MissingCommitment)?;
+        let data_root = &self.data_commitment.data_root;
+        for (proof, root) in
+            [(&proof.replica, replica_root), (&proof.data, data_root)]
+        {
+            proof
+                .verify(root, n_blocks, challenge_index, &proof.path[0])
+                .map_err(|e| {
+                    VerificationError::from_merkle_error(e, proof, root, depth)
+                })?;
+        }
+        Ok(())
+    }
+
+    /// Checks that `proof` opens the block at `challenge_index` from a tree
+    /// of `depth` layers with the Verifier's arity.
+    fn check_shape(
+        &self,
+        proof: &MerkleProof,
+        challenge_index: usize,
+        depth: usize,
+    ) -> VerificationResult {
+        if proof.arity != self.params().arity {
+            return Err(VerificationError::UnexpectedArity {
+                challenge_index,
+                expected: self.params().arity,
+                actual: proof.arity,
+            });
+        }
+        if proof.challenge_index != challenge_index
+            || challenge_index >= self.n_blocks()
+            || proof.depth() != Some(depth)
+        {
+            return Err(VerificationError::MalformedMerkleProof {
+                challenge_index,
+                expected_depth: depth,
+                depth: proof.depth(),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::params::Space;
+
+    /// A replica of `data` and a Verifier that has received its commitment.
+    fn committed(data: &[u8]) -> (Replica, ReplicaVerifier<StdRng>) {
+        let params = ProtoParams::new(Space::Kbs(8)).unwrap();
+        let (nonce, prover_id) = (vec![1, 2, 3], vec![4, 5]);
+        let replica = Replica::encode(
+            params.clone(),
+            nonce.clone(),
+            prover_id.clone(),
+            data,
+        )
+        .unwrap();
+        let data_commitment = DataCommitment::new(data, params.arity);
+        assert_eq!(replica.data_commitment(), data_commitment);
+        let rng = StdRng::seed_from_u64(0);
+        let mut verifier = ReplicaVerifier::with_rng(
+            params,
+            nonce,
+            prover_id,
+            data_commitment,
+            rng,
+        )
+        .unwrap();
+        let commitment = replica.commitment();
+        verifier
+            .set_commitment(commitment.edges, commitment.replica_root)
+            .unwrap();
+        (replica, verifier)
+    }
+
+    fn data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7) as u8).collect()
+    }
+
+    #[test]
+    fn replicas_decode_to_their_data() {
+        for len in &[0, 1, DIGEST_LENGTH, 5 * DIGEST_LENGTH + 3] {
+            let (replica, _) = committed(&data(*len));
+            assert_eq!(replica.decode(), data(*len));
+        }
+    }
+
+    #[test]
+    fn honest_replicas_verify() {
+        let (replica, mut verifier) = committed(&data(20 * DIGEST_LENGTH));
+        let challenge = verifier.gen_challenge();
+        assert_eq!(challenge.len(), verifier.params().l0.min(20));
+        let proofs = replica.create_proofs(&challenge).unwrap();
+        assert_eq!(verifier.verify_proofs(&proofs), Ok(()));
+    }
+
+    #[test]
+    fn tampered_blocks_are_rejected() {
+        let (replica, mut verifier) = committed(&data(20 * DIGEST_LENGTH));
+
+        // A replica block that does not decode to the data.
+        let challenge = verifier.gen_challenge();
+        let mut proofs = replica.create_proofs(&challenge).unwrap();
+        proofs[0].replica.path[0][0] ^= 1;
+        let err = verifier.verify_proofs(&proofs).unwrap_err();
+        assert!(matches!(err, VerificationError::InvalidReplicaBlock { .. }));
+
+        // A data block, changed to match the tampered replica block, that is
+        // not in the committed data.
+        let challenge = verifier.gen_challenge();
+        let mut proofs = replica.create_proofs(&challenge).unwrap();
+        proofs[0].replica.path[0][0] ^= 1;
+        proofs[0].data.path[0][0] ^= 1;
+        let err = verifier.verify_proofs(&proofs).unwrap_err();
+        assert!(matches!(
+            err,
+            VerificationError::CalculatedRootDoesNotMatchStoredRoot { .. }
+        ));
+    }
+
+    #[test]
+    fn responses_must_arrive_before_the_deadline() {
+        let (replica, verifier) = committed(&data(DIGEST_LENGTH));
+        let clock = ManualClock::new();
+        let deadline = Duration::from_millis(10);
+        let mut verifier =
+            verifier.with_clock(clock.clone()).with_deadline(deadline);
+
+        let challenge = verifier.gen_challenge();
+        let proofs = replica.create_proofs(&challenge).unwrap();
+        clock.advance(deadline);
+        assert_eq!(verifier.verify_proofs(&proofs), Ok(()));
+
+        let challenge = verifier.gen_challenge();
+        let proofs = replica.create_proofs(&challenge).unwrap();
+        let elapsed = deadline + Duration::from_nanos(1);
+        clock.advance(elapsed);
+        assert_eq!(
+            verifier.verify_proofs(&proofs),
+            Err(VerificationError::ResponseTooLate { elapsed, deadline })
+        );
+    }
+
+    #[test]
+    fn data_must_fit_in_the_final_column() {
+        let params = ProtoParams::new(Space::Kbs(8)).unwrap();
+        let max_len = params.n * DIGEST_LENGTH;
+        let too_large = ReplicaError::DataTooLarge { max_len };
+        let data = vec![0; max_len + 1];
+
+        let err = Replica::encode(params.clone(), vec![1], vec![2], &data)
+            .unwrap_err();
+        assert_eq!(err.to_string(), Error::Replica(too_large).to_string());
+
+        let data_commitment = DataCommitment::new(&data, params.arity);
+        let rng = StdRng::seed_from_u64(0);
+        let err = ReplicaVerifier::with_rng(
+            params.clone(),
+            vec![1],
+            vec![2],
+            data_commitment,
+            rng,
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Replica(ReplicaError::DataTooLarge { .. })
+        ));
+
+        let data = vec![0; max_len];
+        assert!(Replica::encode(params, vec![1], vec![2], &data).is_ok());
+    }
+}
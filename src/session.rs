@@ -0,0 +1,231 @@
+//! Typestate wrappers around the Prover and the Verifier that only allow the
+//! protocol's messages to be exchanged in order:
+//!
+//! `AwaitingCommitment` -> `Challenged` -> `Verified`
+//!
+//! Each transition consumes the session, so a Verifier cannot be asked to
+//! verify before it has received the Prover's commitment, and a challenge can
+//! only be issued and answered once per session.
+//!
+//! ```
+//! # use pots::params::{ProtoParams, Space};
+//! # use pots::prover::{Commitment, Prover};
+//! # use pots::session::{ProverSession, VerifierSession};
+//! # fn main() -> pots::Result<()> {
+//! # let params = ProtoParams::new(Space::Kbs(8))?;
+//! # let (nonce, prover_id) = (vec![1, 2, 3], vec![4, 5]);
+//! let verifier =
+//!     VerifierSession::new(params.clone(), nonce.clone(), prover_id.clone())?;
+//! let prover = ProverSession::new(Prover::new(params, nonce, prover_id)?);
+//! let Commitment { edges, merkle_root, .. } = prover.commitment();
+//! let (verifier, challenge) =
+//!     verifier.receive_commitment(edges, merkle_root)?;
+//! let (_prover, proofs) = prover.receive_challenge(challenge).respond();
+//! verifier.verify(&proofs?).into_result()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! A Verifier cannot verify proofs before it has received a commitment:
+//!
+//! ```compile_fail
+//! # use pots::params::{ProtoParams, Space};
+//! # use pots::session::VerifierSession;
+//! # fn main() -> pots::Result<()> {
+//! # let params = ProtoParams::new(Space::Kbs(8))?;
+//! let verifier = VerifierSession::new(params, vec![1, 2, 3], vec![4, 5])?;
+//! verifier.verify(&[]);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! and a challenge cannot be answered twice:
+//!
+//! ```compile_fail
+//! # use pots::params::{ProtoParams, Space};
+//! # use pots::prover::Prover;
+//! # use pots::session::ProverSession;
+//! # fn main() -> pots::Result<()> {
+//! # let params = ProtoParams::new(Space::Kbs(8))?;
+//! # let prover = Prover::new(params, vec![1, 2, 3], vec![4, 5])?;
+//! # let challenge = vec![0];
+//! let prover = ProverSession::new(prover).receive_challenge(challenge);
+//! let (_, proofs) = prover.respond();
+//! let (_, proofs_again) = prover.respond();
+//! # Ok(())
+//! # }
+//! ```
+
+use std::time::Duration;
+
+use rand::rngs::OsRng;
+use rand::{CryptoRng, RngCore};
+
+use crate::clock::Clock;
+use crate::error::Result;
+use crate::graph::Edges;
+use crate::merkle::{MerkleLabel, MerkleProof};
+use crate::params::ProtoParams;
+use crate::prover::{Commitment, Prover};
+use crate::verifier::{VerificationResult, Verifier};
+
+/// The Prover has not yet committed to its graph.
+#[derive(Debug)]
+pub struct AwaitingCommitment;
+
+/// The Prover has committed to its graph and has been challenged.
+#[derive(Debug)]
+pub struct Challenged {
+    challenge: Vec<usize>,
+}
+
+/// The Verifier has checked the Prover's answer to the challenge. (The
+/// Prover's session ends once it has answered the challenge.)
+#[derive(Debug)]
+pub struct Verified {
+    result: VerificationResult,
+}
+
+#[derive(Debug)]
+pub struct VerifierSession<S, R = OsRng> {
+    verifier: Verifier<R>,
+    state: S,
+}
+
+impl VerifierSession<AwaitingCommitment> {
+    pub fn new(
+        params: ProtoParams,
+        nonce: Vec<u8>,
+        prover_id: Vec<u8>,
+    ) -> Result<Self> {
+        Ok(VerifierSession {
+            verifier: Verifier::new(params, nonce, prover_id)?,
+            state: AwaitingCommitment,
+        })
+    }
+}
+
+impl<R: RngCore + CryptoRng> VerifierSession<AwaitingCommitment, R> {
+    pub fn with_rng(
+        params: ProtoParams,
+        nonce: Vec<u8>,
+        prover_id: Vec<u8>,
+        rng: R,
+    ) -> Result<Self> {
+        Ok(VerifierSession {
+            verifier: Verifier::with_rng(params, nonce, prover_id, rng)?,
+            state: AwaitingCommitment,
+        })
+    }
+
+    pub fn nonce(&self) -> &[u8] {
+        self.verifier.nonce()
+    }
+
+    /// Sets the clock used to time the Prover's response.
+    pub fn with_clock(self, clock: impl Clock + 'static) -> Self {
+        VerifierSession {
+            verifier: self.verifier.with_clock(clock),
+            state: AwaitingCommitment,
+        }
+    }
+
+    /// Rejects a response that arrives more than `deadline` after the
+    /// challenge was issued.
+    pub fn with_deadline(self, deadline: Duration) -> Self {
+        VerifierSession {
+            verifier: self.verifier.with_deadline(deadline),
+            state: AwaitingCommitment,
+        }
+    }
+
+    /// Accepts the Prover's commitment and challenges it, returning the
+    /// challenge to send to the Prover. Fails if the Prover's graph does not
+    /// match the Verifier's parameters.
+    pub fn receive_commitment(
+        mut self,
+        edges: Edges,
+        merkle_root: MerkleLabel,
+    ) -> Result<(VerifierSession<Challenged, R>, Vec<usize>)> {
+        self.verifier.set_graph_description(edges, merkle_root)?;
+        let challenge = self.verifier.gen_challenge();
+        let session = VerifierSession {
+            verifier: self.verifier,
+            state: Challenged { challenge: challenge.clone() },
+        };
+        Ok((session, challenge))
+    }
+}
+
+impl<R: RngCore + CryptoRng> VerifierSession<Challenged, R> {
+    pub fn challenge(&self) -> &[usize] {
+        &self.state.challenge
+    }
+
+    /// Checks the Prover's answer to the challenge. Proofs that do not answer
+    /// exactly the issued challenge are rejected.
+    pub fn verify(
+        mut self,
+        proofs: &[MerkleProof],
+    ) -> VerifierSession<Verified, R> {
+        let result = self.verifier.verify_proofs(proofs);
+        VerifierSession {
+            verifier: self.verifier,
+            state: Verified { result },
+        }
+    }
+}
+
+impl<R> VerifierSession<Verified, R> {
+    pub fn result(&self) -> &VerificationResult {
+        &self.state.result
+    }
+
+    pub fn into_result(self) -> VerificationResult {
+        self.state.result
+    }
+}
+
+#[derive(Debug)]
+pub struct ProverSession<S> {
+    prover: Prover,
+    state: S,
+}
+
+impl ProverSession<AwaitingCommitment> {
+    pub fn new(prover: Prover) -> Self {
+        ProverSession {
+            prover,
+            state: AwaitingCommitment,
+        }
+    }
+
+    /// The commitment to send to the Verifier.
+    pub fn commitment(&self) -> Commitment {
+        self.prover.commitment()
+    }
+
+    pub fn receive_challenge(
+        self,
+        challenge: Vec<usize>,
+    ) -> ProverSession<Challenged> {
+        ProverSession {
+            prover: self.prover,
+            state: Challenged { challenge },
+        }
+    }
+}
+
+impl ProverSession<Challenged> {
+    pub fn challenge(&self) -> &[usize] {
+        &self.state.challenge
+    }
+
+    /// Answers the challenge, ending the session. Returns the proofs to send
+    /// to the Verifier along with the Prover, so that the Prover can take
+    /// part in another session even if the challenge could not be answered.
+    pub fn respond(mut self) -> (Prover, Result<Vec<MerkleProof>>) {
+        let proofs = self.prover.create_proofs(&self.state.challenge);
+        (self.prover, proofs)
+    }
+}
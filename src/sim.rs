@@ -0,0 +1,580 @@
+//! Cheating Provers and a harness that measures how often the Verifier
+//! accepts them.
+//!
+//! Each adversary starts from an honest Prover's graph and then cheats in one
+//! way: by storing only some of its labels, by dropping whole columns of
+//! labels, or by committing to random (forged) labels instead of computing
+//! them. `run` challenges an adversary over many sessions, charging it
+//! `LABEL_TIME` for every label it recomputes against the Verifier's response
+//! deadline, and compares the empirical acceptance rate with the probability
+//! that a challenge sampled from the parameters' distribution avoids every
+//! label the adversary cannot answer, and with the bound that the parameters'
+//! challenge size was chosen for.
+
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use rand::seq::index;
+use rand::{CryptoRng, RngCore};
+
+use crate::challenge::{self, ChallengeDistribution};
+use crate::clock::ManualClock;
+use crate::error::Result;
+use crate::graph::{self, Edges, LabelMatrix, VertexLabel};
+use crate::hasher::{Hasher, DIGEST_LENGTH};
+use crate::merkle::{MerkleProof, MerkleTree};
+use crate::params::{ProtoParams, ResponseAllowance};
+use crate::prover::{Commitment, Prover};
+use crate::verifier::{VerificationError, Verifier};
+
+/// The nominal time taken to compute one label, which the simulated clock is
+/// advanced by for every label an adversary recomputes.
+pub const LABEL_TIME: Duration = Duration::from_micros(1);
+
+/// A Prover that may deviate from the protocol.
+pub trait Adversary {
+    /// The commitment sent to the Verifier.
+    fn commitment(&self) -> Commitment;
+
+    /// Answers the Verifier's challenge.
+    fn create_proofs(
+        &mut self,
+        challenge: &[usize],
+    ) -> Result<Vec<MerkleProof>>;
+
+    /// Returns `true` if the adversary answers the committed label at `index`
+    /// correctly (whether or not it has to recompute it).
+    fn can_answer(&self, index: usize) -> bool;
+
+    /// The number of labels the adversary has recomputed so far.
+    fn n_recomputed_labels(&self) -> usize {
+        0
+    }
+}
+
+/// What an adversary does when it is challenged on a label that it did not
+/// store.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MissingLabels {
+    /// Answer with a random label.
+    Guess,
+    /// Recompute the label from the stored labels it depends on.
+    Recompute,
+}
+
+/// An adversary that keeps only a subset of the honest Prover's labels, along
+/// with every internal node of its Merkle tree (so that only the labels
+/// themselves, including unstored siblings of a challenged label, have to be
+/// recomputed).
+#[derive(Debug)]
+pub struct PartialStorage<R> {
+    prover: Prover,
+    stored: Vec<bool>,
+    missing_labels: MissingLabels,
+    n_recomputed: usize,
+    rng: R,
+}
+
+impl<R: RngCore + CryptoRng> PartialStorage<R> {
+    /// Stores a random `fraction` of the Prover's labels.
+    pub fn store_fraction(
+        prover: Prover,
+        fraction: f32,
+        missing_labels: MissingLabels,
+        mut rng: R,
+    ) -> Self {
+        let n_labels = prover.params().n * prover.params().k;
+        let n_stored = ((n_labels as f32) * fraction).round() as usize;
+        let n_stored = n_stored.min(n_labels);
+        let mut stored = vec![false; n_labels];
+        for index in index::sample(&mut rng, n_labels, n_stored).into_iter() {
+            stored[index] = true;
+        }
+        PartialStorage {
+            prover,
+            stored,
+            missing_labels,
+            n_recomputed: 0,
+            rng,
+        }
+    }
+
+    /// Stores every label except for those in the columns `dropped_cols`.
+    pub fn drop_columns(
+        prover: Prover,
+        dropped_cols: &[usize],
+        missing_labels: MissingLabels,
+        rng: R,
+    ) -> Self {
+        let n = prover.params().n;
+        let stored = (0..(n * prover.params().k))
+            .map(|index| !dropped_cols.contains(&(index / n)))
+            .collect();
+        PartialStorage {
+            prover,
+            stored,
+            missing_labels,
+            n_recomputed: 0,
+            rng,
+        }
+    }
+}
+
+impl<R: RngCore + CryptoRng> Adversary for PartialStorage<R> {
+    fn commitment(&self) -> Commitment {
+        self.prover.commitment()
+    }
+
+    fn create_proofs(
+        &mut self,
+        challenge: &[usize],
+    ) -> Result<Vec<MerkleProof>> {
+        let mut recomputed = HashMap::new();
+        let proofs = challenge
+            .iter()
+            .map(|&challenge_index| {
+                let mut proof = self.prover.create_proof(challenge_index)?;
+                if self.stored[challenge_index] {
+                    return Ok(proof);
+                }
+                match self.missing_labels {
+                    MissingLabels::Guess => {
+                        self.rng.fill_bytes(&mut proof.path[0]);
+                    }
+                    MissingLabels::Recompute => {
+                        self.recompute_leaves(&mut proof, &mut recomputed);
+                    }
+                }
+                Ok(proof)
+            })
+            .collect::<Result<_>>()?;
+        self.n_recomputed += recomputed.len();
+        Ok(proofs)
+    }
+
+    fn can_answer(&self, index: usize) -> bool {
+        match self.missing_labels {
+            MissingLabels::Guess => self.stored[index],
+            MissingLabels::Recompute => true,
+        }
+    }
+
+    fn n_recomputed_labels(&self) -> usize {
+        self.n_recomputed
+    }
+}
+
+impl<R> PartialStorage<R> {
+    /// Replaces the leaves of `proof` (the challenged label and its siblings)
+    /// that were not stored with labels recomputed from the stored labels,
+    /// adding every label recomputed along the way to `recomputed`.
+    fn recompute_leaves(
+        &self,
+        proof: &mut MerkleProof,
+        recomputed: &mut HashMap<usize, VertexLabel>,
+    ) {
+        let n_children = proof.arity.n_children();
+        let first_leaf = proof.challenge_index / n_children * n_children;
+        let leaves = (first_leaf..(first_leaf + n_children))
+            .filter(|&leaf| leaf != proof.challenge_index);
+        let n_labels = self.stored.len();
+        let has_siblings = proof.depth().is_some_and(|depth| depth > 0);
+        let siblings = leaves.zip(1..n_children).collect::<Vec<_>>();
+        for (leaf, path_index) in siblings {
+            if has_siblings && leaf < n_labels && !self.stored[leaf] {
+                proof.path[path_index] = self.recompute(leaf, recomputed);
+            }
+        }
+        proof.path[0] = self.recompute(proof.challenge_index, recomputed);
+    }
+
+    /// Recomputes the label at `index` from the stored labels, reusing (and
+    /// adding to) the labels in `recomputed`.
+    fn recompute(
+        &self,
+        index: usize,
+        recomputed: &mut HashMap<usize, VertexLabel>,
+    ) -> VertexLabel {
+        let edges = self.prover.edges();
+        let n = self.prover.params().n;
+
+        // Collect the unstored ancestors of `index`. Parents always have
+        // smaller indices than their children, so labeling the ancestors in
+        // increasing order labels every parent first.
+        let mut missing = HashSet::new();
+        let mut stack = vec![index];
+        while let Some(index) = stack.pop() {
+            if self.stored[index]
+                || recomputed.contains_key(&index)
+                || !missing.insert(index)
+            {
+                continue;
+            }
+            let (col, vertex) = (index / n, index % n);
+            for (parent_col, parent) in edges.parents(col, vertex) {
+                stack.push(parent_col * n + parent);
+            }
+        }
+        let mut missing: Vec<usize> = missing.into_iter().collect();
+        missing.sort_unstable();
+
+        let labels = &self.prover.label_matrix().0;
+        let mut hasher = Hasher::with_prover_id(self.prover.prover_id());
+        for index in missing {
+            let label = graph::label_vertex(
+                edges,
+                self.prover.nonce(),
+                &mut hasher,
+                index / n,
+                index % n,
+                |col, vertex| {
+                    let parent = col * n + vertex;
+                    if self.stored[parent] {
+                        &labels[col][vertex]
+                    } else {
+                        &recomputed[&parent]
+                    }
+                },
+            );
+            recomputed.insert(index, label);
+        }
+        recomputed
+            .get(&index)
+            .cloned()
+            .unwrap_or_else(|| labels[index / n][index % n].clone())
+    }
+}
+
+/// An adversary that commits to random labels for a fraction of the graph's
+/// vertices instead of computing them.
+#[derive(Debug)]
+pub struct Forger {
+    prover: Prover,
+    forged: Vec<bool>,
+}
+
+impl Forger {
+    pub fn new<R: RngCore + CryptoRng>(
+        params: ProtoParams,
+        nonce: Vec<u8>,
+        fraction: f32,
+        rng: &mut R,
+    ) -> Result<Self> {
+        let n_labels = params.n * params.k;
+        let n_forged = ((n_labels as f32) * fraction).round() as usize;
+        let n_forged = n_forged.min(n_labels);
+
+        let edges = Edges::from_params_with_rng(&params, rng)?;
+        let mut label_matrix =
+            LabelMatrix::new(&edges, params.k, &nonce, &[]);
+        let mut forged = vec![false; n_labels];
+        for index in index::sample(rng, n_labels, n_forged).into_iter() {
+            let label = &mut label_matrix.0[index / params.n][index % params.n];
+            *label = vec![0; DIGEST_LENGTH];
+            rng.fill_bytes(label);
+            forged[index] = true;
+        }
+
+        let merkle_tree = MerkleTree::from_label_matrix(
+            &label_matrix,
+            params.arity,
+            usize::MAX,
+        );
+        let prover = Prover::from_parts(
+            params,
+            nonce,
+            vec![],
+            edges,
+            label_matrix,
+            merkle_tree,
+        );
+        Ok(Forger { prover, forged })
+    }
+}
+
+impl Adversary for Forger {
+    fn commitment(&self) -> Commitment {
+        self.prover.commitment()
+    }
+
+    fn create_proofs(
+        &mut self,
+        challenge: &[usize],
+    ) -> Result<Vec<MerkleProof>> {
+        self.prover.create_proofs(challenge)
+    }
+
+    fn can_answer(&self, index: usize) -> bool {
+        !self.forged[index]
+    }
+}
+
+/// The outcome of challenging an adversary over many sessions.
+#[derive(Debug)]
+pub struct SimReport {
+    pub n_sessions: usize,
+    pub n_accepted: usize,
+    /// The number of sessions in which the adversary's proofs arrived after
+    /// the Verifier's response deadline.
+    pub n_late: usize,
+    /// The fraction of sessions in which the Verifier accepted.
+    pub acceptance_rate: f64,
+    /// The probability that a challenge sampled from the parameters'
+    /// distribution contains only labels that the adversary can answer
+    /// correctly (regardless of the deadline).
+    pub theoretical_acceptance: f64,
+    /// The acceptance probability that the parameters' challenge size was
+    /// chosen to stay below for an adversary that discards
+    /// `DETECTED_FRACTION` of the labels (see `ProtoParams::miss_bound`).
+    pub miss_bound: f64,
+    /// The mean number of labels recomputed per session.
+    pub mean_recomputed_labels: f64,
+}
+
+/// Challenges `adversary` in `n_sessions` sessions, each with a challenge set
+/// sampled using `rng`, and reports how often it was accepted. Each session
+/// enforces `ProtoParams::response_deadline` on a simulated clock, which is
+/// advanced by `LABEL_TIME` for every label the adversary recomputes. Fails if
+/// the adversary cannot answer a challenge at all.
+pub fn run<A, R>(
+    adversary: &mut A,
+    n_sessions: usize,
+    rng: &mut R,
+) -> Result<SimReport>
+where
+    A: Adversary,
+    R: RngCore + CryptoRng,
+{
+    let Commitment {
+        params,
+        nonce,
+        prover_id,
+        edges,
+        merkle_root,
+    } = adversary.commitment();
+
+    let n_recomputed_before = adversary.n_recomputed_labels();
+    // The simulated clock only advances while labels are recomputed, so
+    // looking up and sending the answers takes no time.
+    let deadline =
+        params.response_deadline(LABEL_TIME, ResponseAllowance::default());
+    let mut n_accepted = 0;
+    let mut n_late = 0;
+
+    for _ in 0..n_sessions {
+        let clock = ManualClock::new();
+        let mut verifier = Verifier::with_rng(
+            params.clone(),
+            nonce.clone(),
+            prover_id.clone(),
+            &mut *rng,
+        )?
+        .with_clock(clock.clone())
+        .with_deadline(deadline);
+        verifier.set_graph_description(edges.clone(), merkle_root.clone())?;
+        let challenge = verifier.gen_challenge();
+
+        let n_recomputed = adversary.n_recomputed_labels();
+        let proofs = adversary.create_proofs(&challenge)?;
+        let n_recomputed = adversary.n_recomputed_labels() - n_recomputed;
+        clock.advance(LABEL_TIME.mul_f64(n_recomputed as f64));
+
+        match verifier.verify_proofs(&proofs) {
+            Ok(()) => n_accepted += 1,
+            Err(VerificationError::ResponseTooLate { .. }) => n_late += 1,
+            Err(_) => {}
+        }
+    }
+
+    let n_recomputed =
+        adversary.n_recomputed_labels() - n_recomputed_before;
+    let n_sessions_f = n_sessions.max(1) as f64;
+
+    let n = params.n;
+    let mut n_valid_per_col = vec![0; params.k];
+    for index in 0..(n * params.k) {
+        if adversary.can_answer(index) {
+            n_valid_per_col[index / n] += 1;
+        }
+    }
+
+    Ok(SimReport {
+        n_sessions,
+        n_accepted,
+        n_late,
+        acceptance_rate: n_accepted as f64 / n_sessions_f,
+        theoretical_acceptance: acceptance_probability(
+            &params,
+            &n_valid_per_col,
+        ),
+        miss_bound: params.miss_bound(),
+        mean_recomputed_labels: n_recomputed as f64 / n_sessions_f,
+    })
+}
+
+/// The probability that a challenge sampled for `params` contains only
+/// labels that can be answered, where column `c` holds `n_valid_per_col[c]`
+/// such labels. For `DeepWeighted` challenges, whose labels are drawn one at
+/// a time with repeats rejected, this is the (upper bound) probability for
+/// draws with replacement.
+pub fn acceptance_probability(
+    params: &ProtoParams,
+    n_valid_per_col: &[usize],
+) -> f64 {
+    let (n, k) = (params.n, params.k);
+    let n_challenged = challenge::challenge_len(params);
+    match params.distribution {
+        ChallengeDistribution::Uniform => {
+            let n_valid = n_valid_per_col.iter().sum();
+            all_drawn_valid(n * k, n_valid, n_challenged)
+        }
+        ChallengeDistribution::LastColumn => {
+            let n_valid = n_valid_per_col.last().cloned().unwrap_or(0);
+            all_drawn_valid(n, n_valid, n_challenged)
+        }
+        ChallengeDistribution::Stratified => {
+            let per_col = n_challenged / k.max(1);
+            n_valid_per_col
+                .iter()
+                .map(|&n_valid| all_drawn_valid(n, n_valid, per_col))
+                .product()
+        }
+        ChallengeDistribution::DeepWeighted => {
+            let total_weight = (k * (k + 1) / 2) as f64;
+            let valid_mass: f64 = n_valid_per_col
+                .iter()
+                .enumerate()
+                .map(|(col, &n_valid)| {
+                    (col + 1) as f64 / total_weight * n_valid as f64 / n as f64
+                })
+                .sum();
+            valid_mass.powi(n_challenged as i32)
+        }
+    }
+}
+
+/// The probability that `n_drawn` labels sampled without replacement from
+/// `n_labels` labels all fall within a set of `n_valid` labels.
+fn all_drawn_valid(n_labels: usize, n_valid: usize, n_drawn: usize) -> f64 {
+    (0..n_drawn).fold(1.0, |prob, i| {
+        if i >= n_valid {
+            0.0
+        } else {
+            prob * (n_valid - i) as f64 / (n_labels - i) as f64
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::params::{Space, DETECTED_FRACTION};
+
+    /// Enough sessions for the binomial interval below to be a few percent
+    /// wide for the acceptance rates tested here.
+    const N_SESSIONS: usize = 500;
+
+    /// The number of standard deviations that the interval spans on each
+    /// side, which a correct simulation leaves with probability below
+    /// `10^-4`.
+    const Z: f64 = 4.0;
+
+    fn prover() -> Prover {
+        let params = ProtoParams::new(Space::Kbs(4)).unwrap();
+        Prover::new(params, vec![1, 2, 3], vec![4, 5]).unwrap()
+    }
+
+    /// The Wilson score interval for the acceptance probability of a
+    /// simulation that accepted `n_accepted` of `n_sessions` sessions.
+    fn wilson_interval(n_accepted: usize, n_sessions: usize) -> (f64, f64) {
+        let n = n_sessions as f64;
+        let rate = n_accepted as f64 / n;
+        let z2 = Z * Z;
+        let center = (rate + z2 / (2.0 * n)) / (1.0 + z2 / n);
+        let half_width = Z / (1.0 + z2 / n)
+            * (rate * (1.0 - rate) / n + z2 / (4.0 * n * n)).sqrt();
+        (center - half_width, center + half_width)
+    }
+
+    fn assert_accepted_as_predicted(report: &SimReport) {
+        let (low, high) =
+            wilson_interval(report.n_accepted, report.n_sessions);
+        assert!(
+            low <= report.theoretical_acceptance
+                && report.theoretical_acceptance <= high,
+            "{:?} is outside [{}, {}]",
+            report,
+            low,
+            high
+        );
+    }
+
+    #[test]
+    fn recomputed_labels_verify_but_miss_the_deadline() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut adversary = PartialStorage::store_fraction(
+            prover(),
+            0.5,
+            MissingLabels::Recompute,
+            StdRng::seed_from_u64(1),
+        );
+
+        // Without a deadline, recomputing the discarded labels is enough.
+        let Commitment { params, nonce, prover_id, edges, merkle_root } =
+            adversary.commitment();
+        let mut verifier =
+            Verifier::with_rng(params, nonce, prover_id, &mut rng).unwrap();
+        verifier.set_graph_description(edges, merkle_root).unwrap();
+        let challenge = verifier.gen_challenge();
+        let proofs = adversary.create_proofs(&challenge).unwrap();
+        assert!(adversary.n_recomputed_labels() > 0);
+        assert_eq!(verifier.verify_proofs(&proofs), Ok(()));
+
+        let report = run(&mut adversary, 5, &mut rng).unwrap();
+        assert_eq!(report.theoretical_acceptance, 1.0);
+        assert_eq!(report.n_late, 5);
+        assert_eq!(report.n_accepted, 0);
+    }
+
+    #[test]
+    fn guessing_is_accepted_as_often_as_predicted() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let params = prover().params().clone();
+        // Store all but the last `n_missing` labels.
+        let (n_labels, n_missing) = (params.n * params.k, 5);
+        let mut adversary = PartialStorage {
+            prover: prover(),
+            stored: (0..n_labels)
+                .map(|index| index < n_labels - n_missing)
+                .collect(),
+            missing_labels: MissingLabels::Guess,
+            n_recomputed: 0,
+            rng: StdRng::seed_from_u64(1),
+        };
+        let report = run(&mut adversary, N_SESSIONS, &mut rng).unwrap();
+        // The challenge must miss each of the missing labels.
+        let expected = (0..n_missing)
+            .map(|i| (n_labels - params.l0 - i) as f64 / (n_labels - i) as f64)
+            .product::<f64>();
+        assert!((report.theoretical_acceptance - expected).abs() < 1e-9);
+        assert!(report.n_accepted > 0);
+        assert_accepted_as_predicted(&report);
+    }
+
+    #[test]
+    fn discarding_the_detected_fraction_stays_below_the_miss_bound() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut adversary = PartialStorage::store_fraction(
+            prover(),
+            1.0 - DETECTED_FRACTION as f32,
+            MissingLabels::Guess,
+            StdRng::seed_from_u64(1),
+        );
+        let report = run(&mut adversary, N_SESSIONS, &mut rng).unwrap();
+        assert!(report.theoretical_acceptance <= report.miss_bound);
+        assert_accepted_as_predicted(&report);
+    }
+}
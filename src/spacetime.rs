@@ -0,0 +1,431 @@
+//! Proofs of space-time: a chain of proofs, one per epoch, showing that the
+//! Prover kept its labels over a period of time rather than at one moment.
+//!
+//! Starting from a single commitment, the challenge for each epoch is derived
+//! from the digest of the previous proof in the chain, the epoch number, the
+//! Prover's Merkle root and the randomness that a public beacon publishes for
+//! the epoch. Epoch `e` uses beacon round `first_round + e`, so the Prover
+//! cannot answer an epoch's challenge before that round is published, and
+//! the chain spans as much real time as the beacon takes to publish its
+//! rounds. `first_round` should be the first round published after the
+//! Verifier received the commitment. The Verifier recomputes every
+//! challenge, checks the whole chain and reports which epochs are missing or
+//! invalid.
+
+use serde::{Deserialize, Serialize};
+
+use crate::challenge::{self, Beacon, ChallengeError};
+use crate::error::Result;
+use crate::graph::Edges;
+use crate::hasher::Hasher;
+use crate::merkle::{MerkleLabel, MerkleProof};
+use crate::params::ProtoParams;
+use crate::prover::{Commitment, Prover};
+use crate::verifier::{VerificationError, Verifier};
+
+/// The Prover's answer to the challenge for one epoch.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct EpochProof {
+    pub epoch: u64,
+    /// The beacon round that the epoch's challenge was derived from.
+    pub round: u64,
+    pub proofs: Vec<MerkleProof>,
+}
+
+impl EpochProof {
+    /// The digest that the next epoch's challenge is derived from.
+    pub fn digest(&self) -> Vec<u8> {
+        Hasher::new().epoch_proof_digest(self.epoch, &self.proofs)
+    }
+}
+
+/// Derives the challenge for `epoch` from the digest of the previous proof in
+/// the chain (empty for the first proof) and the beacon's `randomness` for
+/// the epoch's round.
+pub fn derive_challenge(
+    params: &ProtoParams,
+    merkle_root: &MerkleLabel,
+    prev_digest: &[u8],
+    epoch: u64,
+    randomness: &[u8],
+) -> Vec<usize> {
+    let seed =
+        Hasher::new().epoch_seed(prev_digest, epoch, merkle_root, randomness);
+    challenge::sample_from_seed(params, &seed)
+}
+
+/// The beacon round that `epoch`'s challenge is derived from, or `None` if
+/// it would overflow.
+pub fn epoch_round(first_round: u64, epoch: u64) -> Option<u64> {
+    first_round.checked_add(epoch)
+}
+
+#[derive(Debug)]
+pub struct SpaceTimeProver {
+    prover: Prover,
+    first_round: u64,
+    last_epoch: Option<u64>,
+    prev_digest: Vec<u8>,
+}
+
+impl SpaceTimeProver {
+    /// Creates a Prover whose first epoch is challenged by beacon round
+    /// `first_round`.
+    pub fn new(prover: Prover, first_round: u64) -> Self {
+        SpaceTimeProver {
+            prover,
+            first_round,
+            last_epoch: None,
+            prev_digest: vec![],
+        }
+    }
+
+    /// The commitment to send to the Verifier before the first epoch.
+    pub fn commitment(&self) -> Commitment {
+        self.prover.commitment()
+    }
+
+    /// The most recent epoch that has been proven.
+    pub fn last_epoch(&self) -> Option<u64> {
+        self.last_epoch
+    }
+
+    /// Answers the challenge for `epoch`, extending the chain. Fails with
+    /// `UnexpectedEpoch` if `epoch` is not after the most recently proven
+    /// epoch, and with `BeaconRoundUnavailable` if `beacon` has not yet
+    /// published the epoch's round.
+    pub fn prove_epoch(
+        &mut self,
+        epoch: u64,
+        beacon: &dyn Beacon,
+    ) -> Result<EpochProof> {
+        let round = epoch_round(self.first_round, epoch);
+        let round = match round {
+            Some(round) if self.last_epoch.is_none_or(|last| epoch > last) => {
+                round
+            }
+            _ => {
+                return Err(VerificationError::UnexpectedEpoch { epoch }.into())
+            }
+        };
+        let randomness = beacon
+            .randomness(round)
+            .ok_or(ChallengeError::BeaconRoundUnavailable { round })?;
+        let challenge = derive_challenge(
+            self.prover.params(),
+            self.prover.merkle_root(),
+            &self.prev_digest,
+            epoch,
+            &randomness,
+        );
+        let proof = EpochProof {
+            epoch,
+            round,
+            proofs: self.prover.create_proofs(&challenge)?,
+        };
+        self.prev_digest = proof.digest();
+        self.last_epoch = Some(epoch);
+        Ok(proof)
+    }
+
+    pub fn into_prover(self) -> Prover {
+        self.prover
+    }
+}
+
+/// The outcome of checking a chain of epoch proofs.
+#[derive(Debug)]
+pub struct ChainReport {
+    pub n_epochs: u64,
+    pub valid_epochs: Vec<u64>,
+    /// Epochs for which the chain contains no proof.
+    pub missing_epochs: Vec<u64>,
+    /// Epochs whose proof was rejected, along with the reason.
+    pub invalid_epochs: Vec<(u64, VerificationError)>,
+}
+
+impl ChainReport {
+    /// Returns `true` if every epoch was proven.
+    pub fn is_complete(&self) -> bool {
+        self.missing_epochs.is_empty() && self.invalid_epochs.is_empty()
+    }
+}
+
+#[derive(Debug)]
+pub struct SpaceTimeVerifier {
+    verifier: Verifier,
+    merkle_root: Option<MerkleLabel>,
+    first_round: u64,
+}
+
+impl SpaceTimeVerifier {
+    /// Creates a Verifier whose first epoch is challenged by beacon round
+    /// `first_round`, which should not have been published before the
+    /// Prover's commitment was received.
+    pub fn new(
+        params: ProtoParams,
+        nonce: Vec<u8>,
+        prover_id: Vec<u8>,
+        first_round: u64,
+    ) -> Result<Self> {
+        Ok(SpaceTimeVerifier {
+            verifier: Verifier::new(params, nonce, prover_id)?,
+            merkle_root: None,
+            first_round,
+        })
+    }
+
+    pub fn params(&self) -> &ProtoParams {
+        self.verifier.params()
+    }
+
+    pub fn nonce(&self) -> &[u8] {
+        self.verifier.nonce()
+    }
+
+    pub fn set_commitment(
+        &mut self,
+        edges: Edges,
+        merkle_root: MerkleLabel,
+    ) -> Result<()> {
+        self.verifier.set_graph_description(edges, merkle_root.clone())?;
+        self.merkle_root = Some(merkle_root);
+        Ok(())
+    }
+
+    /// Checks `chain` against the epochs `0..n_epochs`. Proofs must be in
+    /// increasing epoch order; a proof for an epoch that is out of order,
+    /// repeated or not below `n_epochs` is reported as invalid, as is one
+    /// whose challenge is not derived from the epoch's beacon round. Each
+    /// proof's challenge is derived from the proof before it in the chain,
+    /// whether or not that proof was valid. Fails if the Prover's commitment
+    /// has not been set.
+    pub fn verify_chain(
+        &mut self,
+        chain: &[EpochProof],
+        n_epochs: u64,
+        beacon: &dyn Beacon,
+    ) -> Result<ChainReport> {
+        let merkle_root = self
+            .merkle_root
+            .clone()
+            .ok_or(VerificationError::MissingCommitment)?;
+        let mut valid_epochs = vec![];
+        let mut invalid_epochs = vec![];
+        let mut proven = vec![false; n_epochs as usize];
+        let mut last_epoch: Option<u64> = None;
+        let mut prev_digest = vec![];
+
+        for proof in chain {
+            let epoch = proof.epoch;
+            let in_order = last_epoch.is_none_or(|last| epoch > last);
+            let expected_round = epoch_round(self.first_round, epoch);
+            let expected_round = match expected_round {
+                Some(round) if in_order && epoch < n_epochs => round,
+                _ => {
+                    let e = VerificationError::UnexpectedEpoch { epoch };
+                    invalid_epochs.push((epoch, e));
+                    continue;
+                }
+            };
+            proven[epoch as usize] = true;
+
+            let result = self
+                .epoch_challenge(
+                    &merkle_root,
+                    &prev_digest,
+                    proof,
+                    expected_round,
+                    beacon,
+                )
+                .and_then(|challenge| {
+                    self.verifier.verify_answers(&challenge, &proof.proofs)
+                });
+            match result {
+                Ok(()) => valid_epochs.push(epoch),
+                Err(e) => invalid_epochs.push((epoch, e)),
+            }
+
+            prev_digest = proof.digest();
+            last_epoch = Some(epoch);
+        }
+
+        let missing_epochs = (0..n_epochs)
+            .filter(|epoch| !proven[*epoch as usize])
+            .collect();
+
+        Ok(ChainReport {
+            n_epochs,
+            valid_epochs,
+            missing_epochs,
+            invalid_epochs,
+        })
+    }
+
+    /// Rederives the challenge that `proof` should answer, checking that it
+    /// was derived from the epoch's beacon round.
+    fn epoch_challenge(
+        &self,
+        merkle_root: &MerkleLabel,
+        prev_digest: &[u8],
+        proof: &EpochProof,
+        expected_round: u64,
+        beacon: &dyn Beacon,
+    ) -> std::result::Result<Vec<usize>, VerificationError> {
+        let round = proof.round;
+        if round != expected_round {
+            return Err(VerificationError::UnexpectedRound {
+                expected: expected_round,
+                round,
+            });
+        }
+        let randomness = beacon
+            .randomness(round)
+            .ok_or(VerificationError::BeaconRoundUnavailable { round })?;
+        Ok(derive_challenge(
+            self.params(),
+            merkle_root,
+            prev_digest,
+            proof.epoch,
+            &randomness,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::params::Space;
+
+    const FIRST_ROUND: u64 = 5;
+
+    /// A beacon that has published `n_rounds` rounds.
+    struct TestBeacon(u64);
+
+    impl Beacon for TestBeacon {
+        fn randomness(&self, round: u64) -> Option<Vec<u8>> {
+            if round < self.0 {
+                Some(round.to_be_bytes().to_vec())
+            } else {
+                None
+            }
+        }
+
+        fn latest_round(&self) -> Option<u64> {
+            self.0.checked_sub(1)
+        }
+    }
+
+    fn setup() -> (SpaceTimeProver, SpaceTimeVerifier) {
+        let params = ProtoParams::new(Space::Kbs(4)).unwrap();
+        let (nonce, prover_id) = (vec![1, 2, 3], vec![4, 5]);
+        let prover =
+            Prover::new(params.clone(), nonce.clone(), prover_id.clone())
+                .unwrap();
+        let commitment = prover.commitment();
+        let mut verifier =
+            SpaceTimeVerifier::new(params, nonce, prover_id, FIRST_ROUND)
+                .unwrap();
+        verifier
+            .set_commitment(commitment.edges, commitment.merkle_root)
+            .unwrap();
+        (SpaceTimeProver::new(prover, FIRST_ROUND), verifier)
+    }
+
+    fn prove(prover: &mut SpaceTimeProver, epochs: &[u64]) -> Vec<EpochProof> {
+        let beacon = TestBeacon(FIRST_ROUND + 4);
+        epochs
+            .iter()
+            .map(|epoch| prover.prove_epoch(*epoch, &beacon).unwrap())
+            .collect()
+    }
+
+    fn invalid_epochs(report: &ChainReport) -> Vec<u64> {
+        report.invalid_epochs.iter().map(|(epoch, _)| *epoch).collect()
+    }
+
+    #[test]
+    fn valid_chains_are_complete() {
+        let (mut prover, mut verifier) = setup();
+        let chain = prove(&mut prover, &[0, 1, 2, 3]);
+        let beacon = TestBeacon(FIRST_ROUND + 4);
+        let report = verifier.verify_chain(&chain, 4, &beacon).unwrap();
+        assert!(report.is_complete(), "{:?}", report);
+        assert_eq!(report.valid_epochs, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn missing_epochs_are_reported() {
+        let (mut prover, mut verifier) = setup();
+        let chain = prove(&mut prover, &[0, 1, 3]);
+        let beacon = TestBeacon(FIRST_ROUND + 4);
+        let report = verifier.verify_chain(&chain, 4, &beacon).unwrap();
+        assert!(!report.is_complete());
+        assert_eq!(report.valid_epochs, vec![0, 1, 3]);
+        assert_eq!(report.missing_epochs, vec![2]);
+    }
+
+    #[test]
+    fn out_of_order_epochs_are_rejected() {
+        let (mut prover, mut verifier) = setup();
+        let mut chain = prove(&mut prover, &[0, 1, 2]);
+        chain.swap(1, 2);
+        let beacon = TestBeacon(FIRST_ROUND + 4);
+        let report = verifier.verify_chain(&chain, 3, &beacon).unwrap();
+        // Epoch 2 is checked as if it followed epoch 0, so its challenge no
+        // longer matches the one the Prover answered.
+        assert_eq!(invalid_epochs(&report), vec![2, 1]);
+        assert_eq!(
+            report.invalid_epochs[1].1,
+            VerificationError::UnexpectedEpoch { epoch: 1 }
+        );
+        assert_eq!(report.valid_epochs, vec![0]);
+        assert_eq!(report.missing_epochs, vec![1]);
+    }
+
+    #[test]
+    fn tampered_epochs_break_the_chain_after_them() {
+        let (mut prover, mut verifier) = setup();
+        let mut chain = prove(&mut prover, &[0, 1, 2, 3]);
+        chain[1].proofs[0].path[0][0] ^= 1;
+        let beacon = TestBeacon(FIRST_ROUND + 4);
+        let report = verifier.verify_chain(&chain, 4, &beacon).unwrap();
+        // Epoch 2's challenge is derived from the tampered proof, so it no
+        // longer matches the one the Prover answered.
+        assert_eq!(invalid_epochs(&report), vec![1, 2]);
+        assert_eq!(report.valid_epochs, vec![0, 3]);
+    }
+
+    #[test]
+    fn epochs_must_use_their_own_beacon_round() {
+        let (_, mut verifier) = setup();
+        // A Prover that starts from a round published before the commitment
+        // answers every epoch with the wrong round.
+        let (mut early_prover, _) = setup();
+        early_prover.first_round = FIRST_ROUND - 1;
+        let chain = prove(&mut early_prover, &[0, 1]);
+        let beacon = TestBeacon(FIRST_ROUND + 4);
+        let report = verifier.verify_chain(&chain, 2, &beacon).unwrap();
+        assert!(report.valid_epochs.is_empty());
+        assert_eq!(
+            report.invalid_epochs[0].1,
+            VerificationError::UnexpectedRound {
+                expected: FIRST_ROUND,
+                round: FIRST_ROUND - 1,
+            }
+        );
+    }
+
+    #[test]
+    fn epochs_cannot_be_proven_before_their_round_is_published() {
+        let (mut prover, _) = setup();
+        let beacon = TestBeacon(FIRST_ROUND + 2);
+        assert!(prover.prove_epoch(1, &beacon).is_ok());
+        match prover.prove_epoch(2, &beacon) {
+            Err(crate::Error::Challenge(
+                ChallengeError::BeaconRoundUnavailable { round },
+            )) => assert_eq!(round, FIRST_ROUND + 2),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+}
@@ -1,186 +1,1092 @@
+use std::time::Duration;
+
 use rand::rngs::OsRng;
-use rand::seq::SliceRandom;
+use rand::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
 
-use crate::graph::{Edges, VertexLabel};
+use crate::challenge::{
+    self, Beacon, Challenge, ChallengeError, ChallengeOrigin, ChallengeSource,
+    PublicProof,
+};
+use crate::clock::{Clock, SystemClock};
+use crate::error::Result;
+use crate::graph::{self, Edges, GraphFamily, VertexLabel};
 use crate::hasher::Hasher;
-use crate::merkle::{self, MerkleLabel, MerklePath, MerkleProof};
-use crate::params::ProtoParams;
+use crate::merkle::{self, Arity, MerkleError, MerkleLabel, MerkleProof};
+use crate::params::{ParamsError, ProtoParams};
+use crate::prover;
+use crate::receipt::{Receipt, SigningKey};
 
-#[derive(Debug)]
+/// Why verification failed. Errors about a single proof identify the
+/// challenged label (or replica block) that it opens by `challenge_index`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub enum VerificationError {
-    CalculatedRootDoesNotMatchProof,
-    CalculatedRootDoesNotMatchStoredRoot,
-    InvalidNonSourceLabel,
-    InvalidSourceLabel,
+    /// The beacon has not published the round that the challenge claims to
+    /// be derived from.
+    BeaconRoundUnavailable { round: u64 },
+    /// The root calculated from the proof's path is not the root at the end
+    /// of the path.
+    CalculatedRootDoesNotMatchProof {
+        challenge_index: usize,
+        depth: usize,
+        calculated: MerkleLabel,
+        claimed: MerkleLabel,
+    },
+    /// The root calculated from the proof's path is not the Prover's
+    /// committed root.
+    CalculatedRootDoesNotMatchStoredRoot {
+        challenge_index: usize,
+        depth: usize,
+        calculated: MerkleLabel,
+        expected: MerkleLabel,
+    },
+    InvalidNonSourceLabel {
+        challenge_index: usize,
+        column: usize,
+        expected: VertexLabel,
+        actual: VertexLabel,
+    },
+    /// The opened replica block does not decode to the Verifier's copy of
+    /// the data.
+    InvalidReplicaBlock {
+        challenge_index: usize,
+        expected: VertexLabel,
+        actual: VertexLabel,
+    },
+    InvalidSourceLabel {
+        challenge_index: usize,
+        column: usize,
+        expected: VertexLabel,
+        actual: VertexLabel,
+    },
+    /// No challenge is outstanding: the Verifier has not issued one, or the
+    /// most recent one has already been answered.
+    MissingChallenge,
+    /// The Verifier has not received the Prover's commitment.
+    MissingCommitment,
+    /// The proof's path is not a path of `expected_depth` layers to the
+    /// challenged leaf. `depth` is `None` if the path's length is invalid
+    /// for its arity.
+    MalformedMerkleProof {
+        challenge_index: usize,
+        expected_depth: usize,
+        depth: Option<usize>,
+    },
+    ProofsDoNotAnswerChallenge {
+        expected: Vec<usize>,
+        actual: Vec<usize>,
+    },
+    ResponseTooLate { elapsed: Duration, deadline: Duration },
+    UnexpectedArity {
+        challenge_index: usize,
+        expected: Arity,
+        actual: Arity,
+    },
+    UnexpectedEpoch { epoch: u64 },
+    /// The challenge was derived from beacon `round` rather than from the
+    /// `expected` round.
+    UnexpectedRound { expected: u64, round: u64 },
+}
+
+impl VerificationError {
+    /// Adds the context of `proof` to the error from checking it against
+    /// `root` in a tree of `expected_depth` non-root layers.
+    pub(crate) fn from_merkle_error(
+        e: MerkleError,
+        proof: &MerkleProof,
+        root: &MerkleLabel,
+        expected_depth: usize,
+    ) -> Self {
+        let challenge_index = proof.challenge_index;
+        match e {
+            MerkleError::CalculatedRootDoesNotMatchRoot {
+                depth,
+                calculated,
+            } => VerificationError::CalculatedRootDoesNotMatchStoredRoot {
+                challenge_index,
+                depth,
+                calculated,
+                expected: root.clone(),
+            },
+            MerkleError::CalculatedRootDoesNotMatchProof {
+                depth,
+                calculated,
+            } => VerificationError::CalculatedRootDoesNotMatchProof {
+                challenge_index,
+                depth,
+                calculated,
+                claimed: proof.path.last().cloned().unwrap_or_default(),
+            },
+            _ => VerificationError::MalformedMerkleProof {
+                challenge_index,
+                expected_depth,
+                depth: proof.depth(),
+            },
+        }
+    }
+}
+
+pub type VerificationResult = std::result::Result<(), VerificationError>;
+
+/// The outcome of checking every proof in a response, rather than stopping at
+/// the first failure.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct VerificationReport {
+    pub n_proofs: usize,
+    pub failures: Vec<VerificationError>,
+}
+
+impl VerificationReport {
+    pub fn is_valid(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    /// The first failure, if any.
+    pub fn into_result(self) -> VerificationResult {
+        match self.failures.into_iter().next() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Returns `true` if `proofs` answer each vertex in `challenge`, in order.
+pub fn answers_challenge(challenge: &[usize], proofs: &[MerkleProof]) -> bool {
+    proofs
+        .iter()
+        .map(|proof| proof.challenge_index)
+        .eq(challenge.iter().cloned())
+}
+
+/// Like `answers_challenge`, but returns the mismatch as an error.
+pub fn check_answers(
+    challenge: &[usize],
+    proofs: &[MerkleProof],
+) -> VerificationResult {
+    if answers_challenge(challenge, proofs) {
+        Ok(())
+    } else {
+        Err(VerificationError::ProofsDoNotAnswerChallenge {
+            expected: challenge.to_vec(),
+            actual: proofs.iter().map(|proof| proof.challenge_index).collect(),
+        })
+    }
+}
+
+/// A challenge saved by `Verifier::save_challenge`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct SavedChallenge {
+    pub indices: Vec<usize>,
+    pub tag: Vec<u8>,
 }
 
-pub type VerificationResult = Result<(), VerificationError>;
+/// Compares two tags in time that does not depend on where they differ.
+fn tags_match(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len()
+        && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
 
+/// The Verifier samples its challenges using `R`, which defaults to the
+/// operating system's RNG. If a deadline is set, proofs that arrive later than
+/// the deadline after the challenge was issued are rejected.
+///
+/// Each issued challenge can be answered once: verifying proofs consumes the
+/// outstanding challenge, and issuing a new challenge replaces it. The
+/// `session` module's typestate wrappers are the safe way to drive a
+/// Verifier, since they also enforce the order of the protocol's messages.
 #[derive(Debug)]
-pub struct Verifier {
+pub struct Verifier<R = OsRng> {
     params: ProtoParams,
     nonce: Vec<u8>,
+    prover_id: Vec<u8>,
     edges: Option<Edges>,
     merkle_root: Option<MerkleLabel>,
-    rng: OsRng,
+    /// The digest of the Prover's commitment, which public challenges are
+    /// derived from.
+    commitment_digest: Option<Vec<u8>>,
+    rng: R,
     hasher: Hasher,
-    challenge: Vec<usize>,
+    /// The outstanding challenge, if any.
+    challenge: Option<Challenge>,
+    clock: Box<dyn Clock>,
+    deadline: Option<Duration>,
+    /// When the most recent challenge was issued, if a deadline is set.
+    challenged_at: Option<Duration>,
 }
 
 impl Verifier {
-    pub fn new(params: ProtoParams, nonce: Vec<u8>) -> Self {
-        Verifier {
+    /// Creates a Verifier that only accepts proofs from the Prover
+    /// `prover_id`.
+    pub fn new(
+        params: ProtoParams,
+        nonce: Vec<u8>,
+        prover_id: Vec<u8>,
+    ) -> Result<Self> {
+        let rng = OsRng::new()?;
+        Verifier::with_rng(params, nonce, prover_id, rng)
+    }
+}
+
+impl<R: RngCore + CryptoRng> Verifier<R> {
+    /// Creates a Verifier that samples its challenges using `rng`; seeding
+    /// `rng` makes the Verifier's challenges reproducible. Fails if `params`
+    /// are invalid (see `ProtoParams::validate`).
+    pub fn with_rng(
+        params: ProtoParams,
+        nonce: Vec<u8>,
+        prover_id: Vec<u8>,
+        rng: R,
+    ) -> Result<Self> {
+        params.validate()?;
+        let hasher = Hasher::with_prover_id(&prover_id);
+        Ok(Verifier {
             params,
             nonce,
+            prover_id,
             edges: None,
             merkle_root: None,
-            rng: OsRng::new().unwrap(),
-            hasher: Hasher::new(),
-            challenge: vec![],
-        }
+            commitment_digest: None,
+            rng,
+            hasher,
+            challenge: None,
+            clock: Box::new(SystemClock),
+            deadline: None,
+            challenged_at: None,
+        })
+    }
+
+    /// Sets the clock used to time the Prover's responses.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    /// Rejects proofs that arrive more than `deadline` after the challenge
+    /// was issued (see `ProtoParams::response_deadline`). Only challenges
+    /// that the Verifier issues itself are timed.
+    pub fn with_deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    pub fn params(&self) -> &ProtoParams {
+        &self.params
     }
 
     pub fn nonce(&self) -> &[u8] {
         &self.nonce
     }
 
-    fn merkle_root(&self) -> &MerkleLabel {
-        self.merkle_root.as_ref().unwrap()
+    /// The identity of the Prover whose proofs this Verifier accepts.
+    pub fn prover_id(&self) -> &[u8] {
+        &self.prover_id
+    }
+
+    /// The outstanding challenge, if one has been issued and not yet
+    /// answered.
+    pub fn challenge(&self) -> Option<&Challenge> {
+        self.challenge.as_ref()
     }
 
-    fn edges(&self) -> &Edges {
-        self.edges.as_ref().unwrap()
+    pub(crate) fn rng_mut(&mut self) -> &mut R {
+        &mut self.rng
     }
 
+    fn edges(&self) -> std::result::Result<&Edges, VerificationError> {
+        self.edges.as_ref().ok_or(VerificationError::MissingCommitment)
+    }
+
+    fn merkle_root(
+        &self,
+    ) -> std::result::Result<&MerkleLabel, VerificationError> {
+        self.merkle_root
+            .as_ref()
+            .ok_or(VerificationError::MissingCommitment)
+    }
+
+    /// Sets the Prover's graph and Merkle root. The graph is always labeled
+    /// using the Verifier's `LabelingMode`, so a Prover that labeled its graph
+    /// in a different mode fails verification. Fails if the graph is
+    /// malformed, was not sampled for the Verifier's parameters or does not
+    /// reach their minimum spectral gap.
     pub fn set_graph_description(
         &mut self,
         edges: Edges,
         merkle_root: MerkleLabel,
-    ) {
+    ) -> Result<()> {
+        prover::check_graph(&self.params, &edges)?;
+        // Butterfly graphs have no expander to measure, and are sampled
+        // without a minimum gap.
+        match self.params.min_spectral_gap {
+            Some(min_spectral_gap)
+                if self.params.family != GraphFamily::Butterfly =>
+            {
+                let spectral_gap = edges.spectral_gap();
+                if spectral_gap < min_spectral_gap {
+                    return Err(ParamsError::SpectralGapTooSmall {
+                        spectral_gap,
+                        min_spectral_gap,
+                    }
+                    .into());
+                }
+            }
+            _ => {}
+        }
+        let edges = edges.with_labeling(self.params.labeling);
+        self.commitment_digest = Some(prover::commitment_digest(
+            &self.params,
+            &self.nonce,
+            &self.prover_id,
+            &edges,
+            &merkle_root,
+        )?);
         self.edges = Some(edges);
         self.merkle_root = Some(merkle_root);
+        Ok(())
     }
 
+    /// Samples `l0` distinct label indices according to the parameters'
+    /// `ChallengeDistribution`, using memory proportional to `l0` rather than
+    /// to the number of labels. Replaces any outstanding challenge.
     pub fn gen_challenge(&mut self) -> Vec<usize> {
-        let n_total = self.params.n * self.params.k;
-        let mut indices: Vec<usize> = (0..n_total).collect();
-        indices.shuffle(&mut self.rng);
-        let challenge_indices: Vec<usize> = indices
-            .iter()
-            .take(self.params.l0)
-            .cloned()
-            .collect();
-        self.challenge = challenge_indices.clone();
-        challenge_indices
+        let indices = challenge::sample_challenge(&self.params, &mut self.rng);
+        self.issue(Challenge {
+            indices: indices.clone(),
+            origin: ChallengeOrigin::Random,
+        });
+        indices
+    }
+
+    /// Draws the challenge from `source` instead of the Verifier's RNG.
+    pub fn gen_challenge_from(
+        &mut self,
+        source: &mut impl ChallengeSource,
+    ) -> Result<Challenge> {
+        let digest = self.commitment_digest()?;
+        let challenge = source.challenge(&self.params, digest)?;
+        self.issue(challenge.clone());
+        Ok(challenge)
     }
 
+    /// Saves the outstanding challenge (e.g. between invocations of the
+    /// command line tool), tagged under `key` so that only a Verifier holding
+    /// `key` and the same commitment can restore it. `key` must be kept
+    /// secret from the Prover.
+    pub fn save_challenge(&self, key: &[u8]) -> Result<SavedChallenge> {
+        let challenge = self
+            .challenge
+            .as_ref()
+            .ok_or(VerificationError::MissingChallenge)?;
+        let digest = self.commitment_digest()?;
+        let tag = Hasher::new().challenge_tag(key, digest, &challenge.indices);
+        Ok(SavedChallenge {
+            indices: challenge.indices.clone(),
+            tag,
+        })
+    }
+
+    /// Makes `saved`, a challenge that this Verifier sampled and saved
+    /// earlier, the outstanding challenge. Fails if its tag does not
+    /// authenticate it under `key` and the Verifier's commitment (so that a
+    /// Prover cannot pick its own challenge), or if it is not a well-formed
+    /// challenge for the Verifier's parameters.
+    pub fn restore_challenge(
+        &mut self,
+        saved: &SavedChallenge,
+        key: &[u8],
+    ) -> Result<()> {
+        let digest = self.commitment_digest()?;
+        let tag = Hasher::new().challenge_tag(key, digest, &saved.indices);
+        if !tags_match(&tag, &saved.tag) {
+            return Err(ChallengeError::InvalidTag.into());
+        }
+        challenge::check_challenge(&self.params, &saved.indices)?;
+        self.issue(Challenge {
+            indices: saved.indices.clone(),
+            origin: ChallengeOrigin::Random,
+        });
+        Ok(())
+    }
+
+    /// Records `challenge` as the outstanding challenge.
+    fn issue(&mut self, challenge: Challenge) {
+        self.challenge = Some(challenge);
+        self.start_deadline();
+    }
+
+    /// Starts timing the response to a challenge, if a deadline is set.
+    pub(crate) fn start_deadline(&mut self) {
+        if self.deadline.is_some() {
+            self.challenged_at = Some(self.clock.now());
+        }
+    }
+
+    fn commitment_digest(
+        &self,
+    ) -> std::result::Result<&[u8], VerificationError> {
+        self.commitment_digest
+            .as_deref()
+            .ok_or(VerificationError::MissingCommitment)
+    }
+
+    /// Fails if a deadline is set and it has passed since the most recent
+    /// challenge was issued.
+    pub(crate) fn check_deadline(&self) -> VerificationResult {
+        if let (Some(deadline), Some(challenged_at)) =
+            (self.deadline, self.challenged_at)
+        {
+            let elapsed = self.clock.now().saturating_sub(challenged_at);
+            if elapsed > deadline {
+                return Err(VerificationError::ResponseTooLate {
+                    elapsed,
+                    deadline,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Takes the outstanding challenge, so that it can only be answered
+    /// once.
+    fn take_challenge(
+        &mut self,
+    ) -> std::result::Result<Challenge, VerificationError> {
+        self.challenge
+            .take()
+            .ok_or(VerificationError::MissingChallenge)
+    }
+
+    /// Checks that `proofs` answer the outstanding challenge, in order, and
+    /// verifies each of them. Fails if no challenge is outstanding; the
+    /// challenge is consumed whether or not verification succeeds.
     pub fn verify_proofs(
         &mut self,
         proofs: &[MerkleProof],
     ) -> VerificationResult {
+        let challenge = self.take_challenge()?;
+        self.merkle_root()?;
+        self.check_deadline()?;
+        self.verify_answers(&challenge.indices, proofs)
+    }
+
+    /// Verifies `proofs` as the answer to `challenge`, which the caller
+    /// derived itself (e.g. from a chain of epoch proofs). The deadline is
+    /// not checked, as the Verifier did not issue `challenge` and so cannot
+    /// time the answer.
+    pub(crate) fn verify_answers(
+        &mut self,
+        challenge: &[usize],
+        proofs: &[MerkleProof],
+    ) -> VerificationResult {
+        self.merkle_root()?;
+        check_answers(challenge, proofs)?;
         for proof in proofs.iter() {
             self.verify_proof(proof)?;
         }
         Ok(())
     }
 
-    fn verify_proof(
+    /// Like `verify_proofs`, but checks every proof, reporting each failure
+    /// rather than stopping at the first one.
+    pub fn verify_proofs_report(
         &mut self,
-        proof: &MerkleProof,
-    ) -> Result<(), VerificationError> {
-        let MerkleProof { challenge_index, path } = proof;
-        let challenge_is_source = challenge_index < &self.params.n;
-
-        if challenge_is_source {
-            let expected_challenge_label = self.hasher.label_source(
-                &self.nonce,
-                *challenge_index,
-            );
-            if path[0] != expected_challenge_label {
-                return Err(VerificationError::InvalidSourceLabel);
-            }
-        } else {
-            let expected_challenge_label = self.pebble_to(*challenge_index);
-            if path[0] != expected_challenge_label {
-                return Err(VerificationError::InvalidNonSourceLabel);
+        proofs: &[MerkleProof],
+    ) -> VerificationReport {
+        let answers = self
+            .take_challenge()
+            .and_then(|challenge| check_answers(&challenge.indices, proofs));
+        if let Err(e) = self.merkle_root() {
+            // Without a commitment, no proof can be checked.
+            return VerificationReport {
+                n_proofs: proofs.len(),
+                failures: vec![e],
+            };
+        }
+        let mut failures: Vec<VerificationError> = self
+            .check_deadline()
+            .err()
+            .into_iter()
+            .chain(answers.err())
+            .collect();
+        for proof in proofs.iter() {
+            if let Err(e) = self.verify_proof(proof) {
+                failures.push(e);
             }
         }
+        VerificationReport {
+            n_proofs: proofs.len(),
+            failures,
+        }
+    }
 
-        self.verify_merkle_path(*challenge_index, &path)
+    /// Rederives the challenge that `proof` answers from its recorded origin
+    /// and verifies it. A proof whose challenge came from private randomness
+    /// must answer this Verifier's outstanding challenge, which it consumes,
+    /// before the deadline. Fiat-Shamir and beacon challenges are not issued
+    /// by the Verifier, so their answers are not timed.
+    /// A proof whose challenge came from a beacon is checked against `beacon`,
+    /// which pairs the beacon with the only round the Verifier accepts (e.g.
+    /// the first round published after it received the commitment), so that
+    /// the Prover cannot pick the round whose challenge suits it best.
+    pub fn verify_public_proof(
+        &mut self,
+        proof: &PublicProof,
+        beacon: Option<(&dyn Beacon, u64)>,
+    ) -> VerificationResult {
+        let digest = self.commitment_digest()?;
+        let challenge = match proof.origin {
+            ChallengeOrigin::Random => {
+                let challenge = self.take_challenge()?;
+                self.check_deadline()?;
+                challenge.indices
+            }
+            ChallengeOrigin::FiatShamir => {
+                challenge::derive_challenge(&self.params, digest, &[])
+            }
+            ChallengeOrigin::Beacon { round } => {
+                let unavailable =
+                    VerificationError::BeaconRoundUnavailable { round };
+                let (beacon, expected) = beacon.ok_or(unavailable.clone())?;
+                if round != expected {
+                    return Err(VerificationError::UnexpectedRound {
+                        expected,
+                        round,
+                    });
+                }
+                let randomness = beacon.randomness(round).ok_or(unavailable)?;
+                challenge::derive_challenge(&self.params, digest, &randomness)
+            }
+        };
+        self.verify_answers(&challenge, &proof.proofs)
     }
 
-    fn verify_merkle_path(
+    /// Checks that `proofs` answer the outstanding challenge and verifies
+    /// them, returning a receipt of the outcome signed with `signing_key`.
+    /// `timestamp` is recorded in the receipt as the time of verification.
+    /// Refuses to sign anything unless the Prover's commitment has been set,
+    /// a challenge is outstanding and there is one proof per challenged
+    /// label.
+    pub fn verify_proofs_with_receipt(
         &mut self,
-        index: usize,
-        path: &MerklePath,
-    ) -> Result<(), VerificationError> {
-        // Hash together the leaf nodes to get their child node's label.
-        let mut child_label = if merkle::is_left(index) {
-            self.hasher.label_merkle_node(&path[0], &path[1])
-        } else {
-            self.hasher.label_merkle_node(&path[1], &path[0])
+        proofs: &[MerkleProof],
+        signing_key: &SigningKey,
+        timestamp: u64,
+    ) -> Result<Receipt> {
+        let merkle_root = self.merkle_root()?.clone();
+        let challenge = self
+            .challenge
+            .clone()
+            .ok_or(VerificationError::MissingChallenge)?;
+        let n_challenged = challenge::challenge_len(&self.params);
+        if challenge.indices.len() != n_challenged
+            || proofs.len() != n_challenged
+        {
+            return Err(VerificationError::ProofsDoNotAnswerChallenge {
+                expected: challenge.indices,
+                actual: proofs.iter().map(|p| p.challenge_index).collect(),
+            }
+            .into());
+        }
+        let result = self.verify_proofs(proofs);
+        let mut receipt = Receipt {
+            params: self.params.clone(),
+            nonce: self.nonce.clone(),
+            prover_id: self.prover_id.clone(),
+            merkle_root,
+            challenge,
+            deadline: self.deadline,
+            result,
+            timestamp,
+            signature: vec![],
         };
+        receipt.sign(signing_key)?;
+        Ok(receipt)
+    }
 
-        // The child node's index in the next layer of the Merkle tree.
-        let mut child_index = index / 2;
+    fn verify_proof(&mut self, proof: &MerkleProof) -> VerificationResult {
+        let MerkleProof { challenge_index, path, arity } = proof;
+        let challenge_index = *challenge_index;
+        let n_labels = self.params.n * self.params.k;
+        let depth = self.depth();
 
-        // Reconstruct the Merkle Tree then calculate its root.
-        for sibling_label in &path[2..(path.len() - 1)] {
-            child_label = if merkle::is_left(child_index) {
-                self.hasher.label_merkle_node(&child_label, sibling_label)
+        if *arity != self.params.arity {
+            return Err(VerificationError::UnexpectedArity {
+                challenge_index,
+                expected: self.params.arity,
+                actual: *arity,
+            });
+        }
+        if challenge_index >= n_labels || proof.depth() != Some(depth) {
+            return Err(VerificationError::MalformedMerkleProof {
+                challenge_index,
+                expected_depth: depth,
+                depth: proof.depth(),
+            });
+        }
+
+        let (column, vertex) = (
+            challenge_index / self.params.n,
+            challenge_index % self.params.n,
+        );
+        let challenge_is_source =
+            self.edges()?.parents(column, vertex).is_empty();
+        let expected = self.pebble_to(challenge_index)?;
+        if path[0] != expected {
+            let actual = path[0].clone();
+            return Err(if challenge_is_source {
+                VerificationError::InvalidSourceLabel {
+                    challenge_index,
+                    column,
+                    expected,
+                    actual,
+                }
             } else {
-                self.hasher.label_merkle_node(sibling_label, &child_label)
-            };
-            child_index /= 2;
+                VerificationError::InvalidNonSourceLabel {
+                    challenge_index,
+                    column,
+                    expected,
+                    actual,
+                }
+            });
         }
-        let calculated_root = child_label;
 
-        if &calculated_root != self.merkle_root() {
-            Err(VerificationError::CalculatedRootDoesNotMatchStoredRoot)
-        } else if &calculated_root != path.last().unwrap() {
-            Err(VerificationError::CalculatedRootDoesNotMatchProof)
-        } else {
-            Ok(())
+        let merkle_root = self
+            .merkle_root
+            .as_ref()
+            .ok_or(VerificationError::MissingCommitment)?;
+        let n_leaves = self.params.n * self.params.k;
+        proof
+            .verify_with_hasher(
+                &mut self.hasher,
+                merkle_root,
+                n_leaves,
+                challenge_index,
+                &path[0],
+            )
+            .map_err(|e| {
+                VerificationError::from_merkle_error(
+                    e,
+                    proof,
+                    merkle_root,
+                    depth,
+                )
+            })
+    }
+
+    /// The number of non-root layers in the Prover's Merkle tree.
+    fn depth(&self) -> usize {
+        let n_leaves = self.params.n * self.params.k;
+        merkle::n_layers(n_leaves, self.params.arity) - 1
+    }
+
+    /// A memory efficient (one column at a time) labeling of the graph up to
+    /// and including the `dest` vertex. Returns the label of the `dest`
+    /// vertex.
+    pub(crate) fn pebble_to(
+        &mut self,
+        dest: usize,
+    ) -> std::result::Result<VertexLabel, VerificationError> {
+        let n = self.params.n;
+        let (stop_col, stop_vertex) = (dest / n, dest % n);
+        let edges = self
+            .edges
+            .as_ref()
+            .ok_or(VerificationError::MissingCommitment)?;
+
+        // Only the vertices in `dest`'s column that `dest` depends on need to
+        // be labeled; in-column parents always precede their children.
+        let mut needed = vec![false; stop_vertex + 1];
+        needed[stop_vertex] = true;
+        for vertex in (0..=stop_vertex).rev() {
+            if !needed[vertex] {
+                continue;
+            }
+            for (parent_col, parent) in edges.parents(stop_col, vertex) {
+                if parent_col == stop_col {
+                    needed[parent] = true;
+                }
+            }
         }
+
+        // Store (at most) two columns worth of labels at a time.
+        let mut prev_labels: Vec<VertexLabel> = vec![];
+        for col in 0..=stop_col {
+            let n_vertices = if col == stop_col { stop_vertex + 1 } else { n };
+            let mut labels: Vec<VertexLabel> = Vec::with_capacity(n_vertices);
+            for vertex in 0..n_vertices {
+                if col == stop_col && needed.get(vertex) == Some(&false) {
+                    labels.push(vec![]);
+                    continue;
+                }
+                let label = graph::label_vertex(
+                    edges,
+                    &self.nonce,
+                    &mut self.hasher,
+                    col,
+                    vertex,
+                    |parent_col, parent| {
+                        if parent_col == col {
+                            &labels[parent]
+                        } else {
+                            &prev_labels[parent]
+                        }
+                    },
+                );
+                labels.push(label);
+            }
+            prev_labels = labels;
+        }
+
+        Ok(prev_labels.pop().unwrap_or_default())
     }
+}
 
-    /// A memory efficient (one expander at a time) labeling of the graph up to
-    /// and including the `dest` vertex. Returns the label of the `dest` vertex.
-    fn pebble_to(&mut self, dest: usize) -> VertexLabel {
-        let stop_col = dest / self.params.n;
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
 
-        if stop_col == 0 {
-            return self.hasher.label_source(&self.nonce, dest);
+    use super::*;
+    use crate::challenge::{BeaconSource, ChallengeDistribution};
+    use crate::clock::ManualClock;
+    use crate::graph::LabelingMode;
+    use crate::params::{ResponseAllowance, Space};
+    use crate::prover::Prover;
+
+    /// A Prover and a Verifier that has received its commitment.
+    fn committed() -> (Prover, Verifier<StdRng>) {
+        let params = ProtoParams::new(Space::Kbs(8)).unwrap();
+        let (nonce, prover_id) = (vec![1, 2, 3], vec![4, 5]);
+        let prover =
+            Prover::new(params.clone(), nonce.clone(), prover_id.clone())
+                .unwrap();
+        let rng = StdRng::seed_from_u64(0);
+        let mut verifier =
+            Verifier::with_rng(params, nonce, prover_id, rng).unwrap();
+        verifier
+            .set_graph_description(
+                prover.edges().clone(),
+                prover.merkle_root().clone(),
+            )
+            .unwrap();
+        (prover, verifier)
+    }
+
+    #[test]
+    fn graphs_must_reach_the_minimum_spectral_gap() {
+        let (prover, _) = committed();
+        let gap = prover.edges().spectral_gap();
+        for (min_spectral_gap, accepted) in &[(gap, true), (gap + 0.01, false)]
+        {
+            let params = prover
+                .params()
+                .clone()
+                .with_min_spectral_gap(*min_spectral_gap);
+            let mut verifier =
+                Verifier::new(params, vec![1, 2, 3], vec![4, 5]).unwrap();
+            let result = verifier.set_graph_description(
+                prover.edges().clone(),
+                prover.merkle_root().clone(),
+            );
+            assert_eq!(result.is_ok(), *accepted);
         }
+    }
 
-        // Store one columns worth of labels at a time.
-        let mut labels: Vec<VertexLabel> = (0..self.params.n)
-            .map(|i| self.hasher.label_source(&self.nonce, i))
-            .collect();
+    #[test]
+    fn invalid_params_are_rejected() {
+        let (prover, _) = committed();
+        for distribution in &[
+            ChallengeDistribution::Stratified,
+            ChallengeDistribution::LastColumn,
+        ] {
+            let mut params =
+                prover.params().clone().with_distribution(*distribution);
+            params.k = 0;
+            let rng = StdRng::seed_from_u64(0);
+            let err =
+                Verifier::with_rng(params, vec![1], vec![2], rng).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "invalid parameters: TooFewColumns { k: 0, min_k: 1 }"
+            );
+        }
+    }
 
-        // Pebble each column up to (but not including) `dest`'s column.
-        for _ in 1..stop_col {
-            labels = (0..self.params.n)
-                .map(|i| {
-                    let parent_labels: Vec<&VertexLabel> = self
-                        .edges()
-                        .get_parents(i)
-                        .iter()
-                        .map(|parent_index| &labels[*parent_index])
-                        .collect();
-                    self.hasher.label_non_source(&parent_labels)
-                })
-                .collect();
-        }
-
-        // Pebble `dest`.
-        let index = dest % self.params.n;
-        let parent_labels: Vec<&VertexLabel> = self
-            .edges()
-            .get_parents(index)
-            .iter()
-            .map(|parent_index| &labels[*parent_index])
-            .collect();
-        self.hasher.label_non_source(&parent_labels)
+    #[test]
+    fn provers_must_label_in_the_verifiers_mode() {
+        let params = ProtoParams::new(Space::Kbs(8)).unwrap();
+        let localized = params.clone().with_labeling(LabelingMode::Localized);
+        let (nonce, prover_id) = (vec![1, 2, 3], vec![4, 5]);
+        let verifier_for = |prover: &Prover| {
+            let rng = StdRng::seed_from_u64(0);
+            let mut verifier = Verifier::with_rng(
+                localized.clone(),
+                nonce.clone(),
+                prover_id.clone(),
+                rng,
+            )
+            .unwrap();
+            verifier
+                .set_graph_description(
+                    prover.edges().clone(),
+                    prover.merkle_root().clone(),
+                )
+                .unwrap();
+            verifier
+        };
+
+        let mut prover =
+            Prover::new(localized.clone(), nonce.clone(), prover_id.clone())
+                .unwrap();
+        assert_eq!(prover.edges().labeling(), LabelingMode::Localized);
+        let mut verifier = verifier_for(&prover);
+        let challenge = verifier.gen_challenge();
+        let proofs = prover.create_proofs(&challenge).unwrap();
+        assert_eq!(verifier.verify_proofs(&proofs), Ok(()));
+
+        // The same graph labeled in parallel.
+        let edges =
+            prover.edges().clone().with_labeling(LabelingMode::Parallel);
+        let mut parallel = Prover::from_edges(
+            params,
+            nonce.clone(),
+            prover_id.clone(),
+            edges,
+            usize::MAX,
+        )
+        .unwrap();
+        assert_ne!(parallel.merkle_root(), prover.merkle_root());
+        let mut verifier = verifier_for(&parallel);
+        let challenge = verifier.gen_challenge();
+        let proofs = parallel.create_proofs(&challenge).unwrap();
+        assert!(verifier.verify_proofs(&proofs).is_err());
+    }
+
+    #[test]
+    fn proofs_only_verify_for_their_provers_identity() {
+        let (mut prover, _) = committed();
+        for (prover_id, accepted) in &[(vec![4, 5], true), (vec![6], false)] {
+            let rng = StdRng::seed_from_u64(0);
+            let mut verifier = Verifier::with_rng(
+                prover.params().clone(),
+                prover.nonce().to_vec(),
+                prover_id.clone(),
+                rng,
+            )
+            .unwrap();
+            verifier
+                .set_graph_description(
+                    prover.edges().clone(),
+                    prover.merkle_root().clone(),
+                )
+                .unwrap();
+            let challenge = verifier.gen_challenge();
+            let proofs = prover.create_proofs(&challenge).unwrap();
+            let result = verifier.verify_proofs(&proofs);
+            assert_eq!(result.is_ok(), *accepted, "{:?}", result);
+        }
+    }
+
+    #[test]
+    fn each_challenge_is_answered_once() {
+        let (mut prover, mut verifier) = committed();
+        assert_eq!(
+            verifier.verify_proofs(&[]),
+            Err(VerificationError::MissingChallenge)
+        );
+
+        let challenge = verifier.gen_challenge();
+        let proofs = prover.create_proofs(&challenge).unwrap();
+        assert_eq!(verifier.verify_proofs(&proofs), Ok(()));
+        assert_eq!(
+            verifier.verify_proofs(&proofs),
+            Err(VerificationError::MissingChallenge)
+        );
+    }
+
+    #[test]
+    fn receipts_need_a_full_answer_to_a_challenge() {
+        let (mut prover, mut verifier) = committed();
+        let signing_key = SigningKey::from_bytes(&[7; 32]);
+        assert!(verifier
+            .verify_proofs_with_receipt(&[], &signing_key, 0)
+            .is_err());
+
+        let challenge = verifier.gen_challenge();
+        let proofs = prover.create_proofs(&challenge).unwrap();
+        assert!(verifier
+            .verify_proofs_with_receipt(&proofs[1..], &signing_key, 0)
+            .is_err());
+        let receipt = verifier
+            .verify_proofs_with_receipt(&proofs, &signing_key, 0)
+            .unwrap();
+        assert!(receipt.is_accepted());
+        assert_eq!(receipt.challenge.indices, challenge);
+        assert_eq!(receipt.challenge.origin, ChallengeOrigin::Random);
+        assert!(receipt.verify(&signing_key.verifying_key()).is_ok());
+    }
+
+    #[test]
+    fn public_proofs_need_a_challenge_they_can_be_checked_against() {
+        let (mut prover, mut verifier) = committed();
+        let random = PublicProof {
+            origin: ChallengeOrigin::Random,
+            proofs: vec![],
+        };
+        assert_eq!(
+            verifier.verify_public_proof(&random, None),
+            Err(VerificationError::MissingChallenge)
+        );
+
+        let mut source = challenge::FiatShamirSource;
+        let proof = prover.create_public_proof(&mut source).unwrap();
+        assert_eq!(verifier.verify_public_proof(&proof, None), Ok(()));
+    }
+
+    /// A beacon that has published `n_rounds` rounds.
+    struct TestBeacon(u64);
+
+    impl Beacon for TestBeacon {
+        fn randomness(&self, round: u64) -> Option<Vec<u8>> {
+            if round < self.0 {
+                Some(round.to_be_bytes().to_vec())
+            } else {
+                None
+            }
+        }
+
+        fn latest_round(&self) -> Option<u64> {
+            self.0.checked_sub(1)
+        }
+    }
+
+    #[test]
+    fn beacon_proofs_must_use_the_expected_round() {
+        let (mut prover, mut verifier) = committed();
+        let mut source = BeaconSource::new(TestBeacon(4)).with_round(2);
+        let proof = prover.create_public_proof(&mut source).unwrap();
+        let beacon: &dyn Beacon = source.beacon();
+        assert_eq!(
+            verifier.verify_public_proof(&proof, Some((beacon, 3))),
+            Err(VerificationError::UnexpectedRound {
+                expected: 3,
+                round: 2,
+            })
+        );
+        assert_eq!(
+            verifier.verify_public_proof(&proof, None),
+            Err(VerificationError::BeaconRoundUnavailable { round: 2 })
+        );
+        assert_eq!(
+            verifier.verify_public_proof(&proof, Some((beacon, 2))),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn commitment_digest_covers_the_graph_and_params() {
+        let (prover, _) = committed();
+        let commitment = prover.commitment();
+        let digest = commitment.digest().unwrap();
+
+        let mut other_edges = commitment.clone();
+        other_edges.edges = Edges::new(
+            other_edges.params.family,
+            other_edges.params.n,
+        )
+        .unwrap();
+        assert_ne!(other_edges.digest().unwrap(), digest);
+
+        let mut other_params = commitment;
+        other_params.params.l0 += 1;
+        assert_ne!(other_params.digest().unwrap(), digest);
+    }
+
+    #[test]
+    fn responses_must_arrive_before_the_deadline() {
+        let (mut prover, verifier) = committed();
+        let clock = ManualClock::new();
+        let allowance = ResponseAllowance {
+            opening_time: Duration::from_micros(10),
+            round_trip: Duration::from_millis(50),
+        };
+        let deadline = verifier
+            .params()
+            .response_deadline(Duration::from_millis(1), allowance);
+        let mut verifier =
+            verifier.with_clock(clock.clone()).with_deadline(deadline);
+
+        let challenge = verifier.gen_challenge();
+        let proofs = prover.create_proofs(&challenge).unwrap();
+        clock.advance(deadline);
+        assert_eq!(verifier.verify_proofs(&proofs), Ok(()));
+
+        let challenge = verifier.gen_challenge();
+        let proofs = prover.create_proofs(&challenge).unwrap();
+        let elapsed = deadline + Duration::from_nanos(1);
+        clock.advance(elapsed);
+        assert_eq!(
+            verifier.verify_proofs(&proofs),
+            Err(VerificationError::ResponseTooLate { elapsed, deadline })
+        );
+
+        // Fiat-Shamir challenges are not issued by the Verifier, so their
+        // answers are not timed against its last challenge.
+        verifier.gen_challenge();
+        clock.advance(elapsed);
+        let mut source = challenge::FiatShamirSource;
+        let proof = prover.create_public_proof(&mut source).unwrap();
+        assert_eq!(verifier.verify_public_proof(&proof, None), Ok(()));
+    }
+
+    #[test]
+    fn reports_name_each_failure_once() {
+        let (mut prover, mut verifier) = committed();
+        let params = verifier.params().clone();
+        let mut uncommitted = Verifier::with_rng(
+            params.clone(),
+            verifier.nonce().to_vec(),
+            verifier.prover_id().to_vec(),
+            StdRng::seed_from_u64(1),
+        )
+        .unwrap();
+        let challenge = uncommitted.gen_challenge();
+        let proofs = prover.create_proofs(&challenge).unwrap();
+        assert_eq!(
+            uncommitted.verify_proofs_report(&proofs).failures,
+            vec![VerificationError::MissingCommitment]
+        );
+
+        // A proof of a tampered sibling fails at the root, at the depth of
+        // the Prover's tree.
+        let challenge = verifier.gen_challenge();
+        let mut proofs = prover.create_proofs(&challenge).unwrap();
+        proofs[0].path[1][0] ^= 1;
+        let depth = merkle::n_layers(params.n * params.k, params.arity) - 1;
+        let failures = verifier.verify_proofs_report(&proofs).failures;
+        assert_eq!(failures.len(), 1);
+        assert!(matches!(
+            failures[0],
+            VerificationError::CalculatedRootDoesNotMatchStoredRoot {
+                depth: failed_depth,
+                ..
+            } if failed_depth == depth
+        ));
+    }
+
+    #[test]
+    fn proofs_must_answer_the_outstanding_challenge() {
+        let (mut prover, mut verifier) = committed();
+        let stale = verifier.gen_challenge();
+        let challenge = verifier.gen_challenge();
+        let proofs = prover.create_proofs(&stale).unwrap();
+        let report = verifier.verify_proofs_report(&proofs);
+        assert_eq!(
+            report.failures,
+            vec![VerificationError::ProofsDoNotAnswerChallenge {
+                expected: challenge,
+                actual: stale,
+            }]
+        );
     }
 }